@@ -0,0 +1,18 @@
+use std::{env, process::Command};
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=RUSTC");
+    // bake the version of the compiler into the macro, for `rustc >= "1.xx"` conditions
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let output = match Command::new(rustc).arg("--version").output() {
+        Ok(output) if output.status.success() => output,
+        _ => return,
+    };
+    // `rustc 1.70.0 (90c541806 2023-05-31)` or `rustc 1.80.0-nightly (..)`
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if let Some(version) = stdout.split_whitespace().nth(1) {
+        let version = version.split('-').next().unwrap_or(version);
+        println!("cargo:rustc-env=PRAGMA_RUSTC_VERSION={}", version);
+    }
+}
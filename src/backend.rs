@@ -0,0 +1,133 @@
+//! Backend selection with `select_backend!`
+//!
+//! ```text
+//! select_backend! { pub mod imp { unix => "imp/unix.rs", _ => "imp/fallback.rs" } }
+//! ```
+//!
+//! expands to a `pragma!` chain that loads the first matching backend under a private name, and a
+//! facade module that re-exports it, so the backend is always reachable as `imp`:
+//!
+//! ```text
+//! ::pragma::pragma! {
+//!     (if unix) mod __pragma_backend_imp = "imp/unix.rs";
+//!     (else) mod __pragma_backend_imp = "imp/fallback.rs";
+//! }
+//! pub mod imp { pub use super::__pragma_backend_imp::*; }
+//! ```
+
+use {
+    crate::{grammar, ParseResult},
+    proc_macro2::{TokenStream, TokenTree},
+    quote::{format_ident, quote},
+    syn::{
+        braced,
+        parse::{Parse, ParseStream, Parser},
+        Attribute, Ident, LitStr, Token, Visibility,
+    },
+};
+
+/// `attrs vis mod name { cond => "path", .., _ => "path" }`
+pub(crate) struct SelectBackend {
+    attrs: Vec<Attribute>,
+    visibility: Visibility,
+    name: Ident,
+    arms: Vec<(TokenStream, LitStr)>,
+    fallback: Option<LitStr>,
+}
+
+impl Parse for SelectBackend {
+    fn parse(input: ParseStream) -> ParseResult<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let visibility: Visibility = input.parse()?;
+        input.parse::<Token![mod]>()?;
+        let name: Ident = input.parse()?;
+        let content;
+        let brace = braced!(content in input);
+
+        let mut arms = Vec::new();
+        let mut fallback = None;
+        while !content.is_empty() {
+            if fallback.is_some() {
+                return Err(content.error("the `_` arm must be the last arm"));
+            }
+            if content.peek(Token![_]) {
+                content.parse::<Token![_]>()?;
+                content.parse::<Token![=>]>()?;
+                fallback = Some(content.parse()?);
+            } else {
+                // the condition is everything up to the `=>`
+                let mut condition = TokenStream::new();
+                while !content.peek(Token![=>]) {
+                    if content.is_empty() {
+                        return Err(content.error("expected `=>` after the condition"));
+                    }
+                    condition.extend(std::iter::once(content.parse::<TokenTree>()?));
+                }
+                content.parse::<Token![=>]>()?;
+                Parser::parse2(
+                    |input: ParseStream| grammar::parse_condition(&input),
+                    condition.clone(),
+                )?;
+                arms.push((condition, content.parse()?));
+            }
+            if !content.is_empty() {
+                content.parse::<Token![,]>()?;
+            }
+        }
+        if arms.is_empty() {
+            return Err(syn::Error::new(
+                brace.span,
+                "expected at least one `condition => \"path\"` arm",
+            ));
+        }
+        Ok(SelectBackend {
+            attrs,
+            visibility,
+            name,
+            arms,
+            fallback,
+        })
+    }
+}
+
+pub(crate) fn expand_select_backend(select: SelectBackend) -> TokenStream {
+    let SelectBackend {
+        attrs,
+        visibility,
+        name,
+        arms,
+        fallback,
+    } = select;
+    let backend = format_ident!("__pragma_backend_{}", name);
+    let arms = arms.iter().enumerate().map(|(i, (condition, path))| {
+        let keyword = if i == 0 {
+            quote! { if }
+        } else {
+            quote! { else if }
+        };
+        quote! { (#keyword #condition) mod #backend = #path; }
+    });
+    let fallback = match fallback {
+        Some(path) => quote! { (else) mod #backend = #path; },
+        None => {
+            // an empty backend keeps the facade from adding errors of its own
+            let msg = format!("no backend of `{}` supports this target", name);
+            quote! {
+                (else) {
+                    compile_error!(#msg);
+                    mod #backend {}
+                }
+            }
+        }
+    };
+    quote! {
+        ::pragma::pragma! {
+            #(#arms)*
+            #fallback
+        }
+        #(#attrs)*
+        #visibility mod #name {
+            pub use super::#backend::*;
+        }
+    }
+}
@@ -0,0 +1,354 @@
+//! Runtime dispatch on `target_feature`s
+//!
+//! ```text
+//! (if target_feature = "avx2" and target_feature = "fma" or runtime) fn sum(x: &[f32]) -> f32 { .. }
+//! ```
+//!
+//! expands to a function `sum` that contains two copies of the body: one compiled with
+//! `#[target_feature(enable = "avx2,fma")]` and a portable one. If the features are enabled at
+//! compile time, the first copy is always used. Otherwise, if they are all x86 features, on x86
+//! and x86_64 the features are detected the first time the function is called, and the result is
+//! cached for later calls. Everywhere else the portable copy is used.
+
+use {
+    crate::{
+        grammar::{CfgValue, ConditionExpr},
+        known, ParseResult,
+    },
+    proc_macro2::{TokenStream, TokenTree},
+    quote::{format_ident, quote, ToTokens},
+    syn::{
+        spanned::Spanned, FnArg, GenericParam, Ident, Item, ItemFn, LitStr, Pat, PatIdent,
+        ReturnType,
+    },
+};
+
+/// A function that is dispatched at runtime
+pub(crate) struct Dispatch {
+    features: Vec<LitStr>,
+    item: ItemFn,
+    args: Vec<Ident>,
+}
+
+/// if `cond` is `<target features> or runtime`, return the `runtime` keyword and the features
+///
+/// Any other condition that ends with `or runtime` refers to a cfg option called `runtime`.
+pub(crate) fn runtime_features(cond: &ConditionExpr) -> Option<(Ident, Vec<LitStr>)> {
+    let (features, runtime) = match cond {
+        ConditionExpr::Any(exprs) => match exprs.split_last() {
+            Some((ConditionExpr::Key(runtime), features)) if runtime == "runtime" => {
+                (features, runtime)
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let features = match features {
+        [ConditionExpr::All(features)] => features.iter().collect(),
+        [feature] => vec![feature],
+        _ => return None,
+    };
+    features
+        .into_iter()
+        .map(|feature| match feature {
+            ConditionExpr::KeyVal(key, value) if key == "target_feature" => {
+                Some(CfgValue::to_lit_str(value))
+            }
+            _ => None,
+        })
+        .collect::<Option<_>>()
+        .map(|features| (runtime.clone(), features))
+}
+
+impl Dispatch {
+    pub(crate) fn new(runtime: &Ident, features: Vec<LitStr>, item: Item) -> ParseResult<Self> {
+        let item = match item {
+            Item::Fn(item) => item,
+            item => {
+                return Err(syn::Error::new(
+                    item.span(),
+                    "`or runtime` can only be used on functions",
+                ))
+            }
+        };
+        if let Some(constness) = &item.sig.constness {
+            return Err(syn::Error::new(
+                constness.span,
+                "`const` functions can't be dispatched at runtime",
+            ));
+        }
+        if let Some(asyncness) = &item.sig.asyncness {
+            return Err(syn::Error::new(
+                asyncness.span,
+                "`async` functions can't be dispatched at runtime",
+            ));
+        }
+        // every argument is forwarded by name
+        let mut args = Vec::new();
+        for input in item.sig.inputs.iter() {
+            let pat = match input {
+                FnArg::Typed(pat) => pat,
+                FnArg::Receiver(receiver) => {
+                    return Err(syn::Error::new(
+                        receiver.span(),
+                        "methods can't be dispatched at runtime",
+                    ))
+                }
+            };
+            match &*pat.pat {
+                Pat::Ident(PatIdent {
+                    by_ref: None,
+                    subpat: None,
+                    ident,
+                    ..
+                }) => args.push(ident.clone()),
+                other => {
+                    return Err(syn::Error::new(
+                        other.span(),
+                        "the arguments of a function that is dispatched at runtime must be plain \
+                         identifiers",
+                    ))
+                }
+            }
+        }
+        let generic_args = item
+            .sig
+            .generics
+            .params
+            .iter()
+            .any(|param| !matches!(param, GenericParam::Lifetime(_)));
+        if generic_args {
+            // the generic arguments are forwarded with a turbofish, which `impl Trait` arguments
+            // don't allow
+            if let Some(arg) = item.sig.inputs.iter().find(contains_impl) {
+                return Err(syn::Error::new(
+                    arg.span(),
+                    "a generic function that is dispatched at runtime can't take `impl Trait` \
+                     arguments",
+                ));
+            }
+        }
+        // the dispatcher returns the result of either copy, and the two copies would have
+        // different opaque types
+        if let ReturnType::Type(_, ty) = &item.sig.output {
+            if contains_impl(ty) {
+                return Err(syn::Error::new(
+                    ty.span(),
+                    "a function that is dispatched at runtime can't return `impl Trait`",
+                ));
+            }
+        }
+        if features.is_empty() {
+            return Err(syn::Error::new(
+                runtime.span(),
+                "`or runtime` needs at least one `target_feature`",
+            ));
+        }
+        Ok(Dispatch {
+            features,
+            item,
+            args,
+        })
+    }
+
+    pub(crate) fn expand(&self) -> TokenStream {
+        let Dispatch {
+            features,
+            item,
+            args,
+        } = self;
+        let ItemFn {
+            attrs, vis, sig, ..
+        } = item;
+        let block = &item.block;
+        let portable = format_ident!("__pragma_portable");
+        let enabled = format_ident!("__pragma_enabled");
+        let dispatch = format_ident!("__pragma_dispatch");
+
+        // the signature of the copies, without the ABI of the outer function
+        let mut inner_sig = sig.clone();
+        inner_sig.abi = None;
+        // the signature of the functions that just forward their arguments
+        let mut forward_sig = inner_sig.clone();
+        for input in forward_sig.inputs.iter_mut() {
+            if let FnArg::Typed(pat) = input {
+                if let Pat::Ident(pat) = &mut *pat.pat {
+                    pat.mutability = None;
+                }
+            }
+        }
+        let mut outer_sig = forward_sig.clone();
+        outer_sig.abi = sig.abi.clone();
+        let (mut portable_sig, mut enabled_sig, mut dispatch_sig) =
+            (inner_sig.clone(), inner_sig, forward_sig);
+        portable_sig.ident = portable.clone();
+        enabled_sig.ident = enabled.clone();
+        enabled_sig.unsafety = Some(Default::default());
+        dispatch_sig.ident = dispatch.clone();
+
+        let generic_args: Vec<TokenStream> = sig
+            .generics
+            .params
+            .iter()
+            .filter_map(|param| match param {
+                GenericParam::Type(param) => Some(param.ident.to_token_stream()),
+                GenericParam::Const(param) => Some(param.ident.to_token_stream()),
+                GenericParam::Lifetime(_) => None,
+            })
+            .collect();
+        let turbofish = if generic_args.is_empty() {
+            quote! {}
+        } else {
+            quote! { ::<#(#generic_args),*> }
+        };
+        let call_portable = if sig.unsafety.is_some() {
+            quote! { unsafe { #portable #turbofish(#(#args),*) } }
+        } else {
+            quote! { #portable #turbofish(#(#args),*) }
+        };
+        let call_enabled = quote! { unsafe { #enabled #turbofish(#(#args),*) } };
+        let enable = LitStr::new(
+            &features
+                .iter()
+                .map(LitStr::value)
+                .collect::<Vec<_>>()
+                .join(","),
+            features[0].span(),
+        );
+        // features of other architectures can't be enabled, let alone detected, on x86
+        let x86 = if features
+            .iter()
+            .all(|feature| known::X86_FEATURES.contains(&&*feature.value()))
+        {
+            quote! { any(target_arch = "x86", target_arch = "x86_64") }
+        } else {
+            quote! { any() }
+        };
+        let statically = quote! { all(#(target_feature = #features),*) };
+
+        quote! {
+            #(#attrs)*
+            #vis #outer_sig {
+                #[cfg(not(#statically))]
+                #[inline(always)]
+                #portable_sig #block
+
+                #[cfg(any(#statically, #x86))]
+                #[target_feature(enable = #enable)]
+                #enabled_sig #block
+
+                #[cfg(#statically)]
+                #[inline(always)]
+                #dispatch_sig {
+                    #call_enabled
+                }
+
+                #[cfg(all(not(#statically), #x86))]
+                #[inline(always)]
+                #dispatch_sig {
+                    use ::core::sync::atomic::{AtomicU8, Ordering};
+                    // 0 if the features haven't been detected yet, 1 if they're missing and 2 if
+                    // they're available
+                    static DETECTED: AtomicU8 = AtomicU8::new(0);
+                    let mut detected = DETECTED.load(Ordering::Relaxed);
+                    if detected == 0 {
+                        detected = if true #(&& ::std::is_x86_feature_detected!(#features))* {
+                            2
+                        } else {
+                            1
+                        };
+                        DETECTED.store(detected, Ordering::Relaxed);
+                    }
+                    if detected == 2 {
+                        #call_enabled
+                    } else {
+                        #call_portable
+                    }
+                }
+
+                #[cfg(not(any(#statically, #x86)))]
+                #[inline(always)]
+                #dispatch_sig {
+                    #call_portable
+                }
+
+                #dispatch #turbofish(#(#args),*)
+            }
+        }
+    }
+}
+
+/// check if an argument or a type uses `impl Trait`
+fn contains_impl(tokens: &impl ToTokens) -> bool {
+    fn scan(tokens: TokenStream) -> bool {
+        tokens.into_iter().any(|token| match token {
+            TokenTree::Ident(ident) => ident == "impl",
+            TokenTree::Group(group) => scan(group.stream()),
+            _ => false,
+        })
+    }
+    scan(tokens.to_token_stream())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{grammar, parse::PragmaInput},
+        syn::parse::{ParseStream, Parser},
+    };
+
+    fn features(condition: &str) -> Option<Vec<String>> {
+        let condition = Parser::parse2(
+            |input: ParseStream| grammar::parse_condition(&input),
+            condition.parse().unwrap(),
+        )
+        .unwrap();
+        runtime_features(&condition)
+            .map(|(_, features)| features.iter().map(LitStr::value).collect())
+    }
+
+    #[test]
+    fn runtime_cfg_option() {
+        assert_eq!(
+            features(r#"target_feature = "avx2" and target_feature = "fma" or runtime"#),
+            Some(vec!["avx2".to_owned(), "fma".to_owned()])
+        );
+        assert_eq!(features("tokio or runtime"), None);
+        assert_eq!(
+            features(r#"target_feature = "avx2" or tokio or runtime"#),
+            None
+        );
+        assert_eq!(features(r#"target_feature = "avx2" or r#runtime"#), None);
+    }
+
+    #[test]
+    fn impl_trait_return_type() {
+        let error = syn::parse_str::<PragmaInput>(
+            r#"(if target_feature = "avx2" or runtime)
+            fn evens(x: u32) -> impl Iterator<Item = u32> { (0..x).filter(|x| x % 2 == 0) }"#,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "a function that is dispatched at runtime can't return `impl Trait`"
+        );
+    }
+
+    #[test]
+    fn runtime_only_on_functions() {
+        for input in [
+            r#"(if target_feature = "avx2" or runtime) mod m {}"#,
+            r#"(if target_feature = "avx2" or runtime) mod m;"#,
+            r#"(if target_feature = "avx2" or runtime) { fn f() {} }"#,
+            r#"if target_feature = "avx2" or runtime; fn f() {}"#,
+        ] {
+            let error = syn::parse_str::<PragmaInput>(input).err().unwrap();
+            assert_eq!(
+                error.to_string(),
+                "`or runtime` can only be used on functions"
+            );
+        }
+    }
+}
@@ -1,110 +1,505 @@
 use {
-    super::ParseResult,
+    super::{kconfig, known, manifest, shorthand, template, ParseResult},
+    proc_macro2::{Delimiter, Group, Span, TokenStream, TokenTree},
     quote::quote,
-    syn::{parse::ParseStream, Ident, LitStr, Token},
+    std::{cell::RefCell, cmp::Ordering, path::PathBuf},
+    syn::{
+        ext::IdentExt,
+        parse::{Parse, ParseStream, Parser},
+        punctuated::Punctuated,
+        Ident, Lit, LitInt, LitStr, Token,
+    },
 };
 
 /// Condition expression AST
+#[derive(Clone)]
 pub(crate) enum ConditionExpr {
     All(Vec<ConditionExpr>),
     Any(Vec<ConditionExpr>),
     Not(Box<ConditionExpr>),
-    KeyVal(Ident, LitStr),
+    /// `a xor b`
+    Xor(Box<ConditionExpr>, Box<ConditionExpr>),
+    /// `a implies b`
+    Implies(Box<ConditionExpr>, Box<ConditionExpr>),
+    /// `exactly_one_of(..)`
+    ExactlyOne(Vec<ConditionExpr>),
+    /// `at_most_one_of(..)`
+    AtMostOne(Vec<ConditionExpr>),
+    KeyVal(Ident, CfgValue),
+    NotEq(Ident, CfgValue),
+    In(Ident, Vec<CfgValue>),
+    /// `key ~ "pattern"`, matched against the table of well-known values for `key`
+    Glob(Ident, LitStr),
+    /// `rustc >= "1.70"`, compared against the version of the compiler at macro expansion time
+    RustcVersion(CmpOp, RustcVersion),
+    /// `env "NAME"` or `env "NAME" = "value"`, evaluated at macro expansion time
+    Env(LitStr, Option<CfgValue>),
+    /// a `CONFIG_` symbol, already evaluated against the `.config` file at `path`
+    Kconfig {
+        holds: bool,
+        path: String,
+    },
+    /// `@name`, a condition defined with `define_condition!`. these are substituted before the
+    /// condition is expanded
+    Named(Ident),
+    /// `name(args..)`, a call to a condition template. these are substituted before the condition
+    /// is expanded
+    Call(Ident, Vec<TokenStream>),
     Key(Ident),
 }
 
+impl ConditionExpr {
+    /// the direct subexpressions of this expression
+    fn children(&self) -> Vec<&ConditionExpr> {
+        match self {
+            ConditionExpr::All(exprs)
+            | ConditionExpr::Any(exprs)
+            | ConditionExpr::ExactlyOne(exprs)
+            | ConditionExpr::AtMostOne(exprs) => exprs.iter().collect(),
+            ConditionExpr::Not(expr) => vec![&**expr],
+            ConditionExpr::Xor(lhs, rhs) | ConditionExpr::Implies(lhs, rhs) => vec![&**lhs, &**rhs],
+            _ => Vec::new(),
+        }
+    }
+    pub(crate) fn children_mut(&mut self) -> Vec<&mut ConditionExpr> {
+        match self {
+            ConditionExpr::All(exprs)
+            | ConditionExpr::Any(exprs)
+            | ConditionExpr::ExactlyOne(exprs)
+            | ConditionExpr::AtMostOne(exprs) => exprs.iter_mut().collect(),
+            ConditionExpr::Not(expr) => vec![&mut **expr],
+            ConditionExpr::Xor(lhs, rhs) | ConditionExpr::Implies(lhs, rhs) => {
+                vec![&mut **lhs, &mut **rhs]
+            }
+            _ => Vec::new(),
+        }
+    }
+    /// call `f` on this expression and all of its subexpressions
+    pub(crate) fn visit(&self, f: &mut impl FnMut(&ConditionExpr)) {
+        f(self);
+        for child in self.children() {
+            child.visit(f);
+        }
+    }
+}
+
+/// The value of a cfg key/value check
+///
+/// `rustc` only accepts string literals, so identifiers and integers are normalized to strings
+/// when emitting the cfg predicate.
+#[derive(Clone)]
+pub(crate) enum CfgValue {
+    Str(LitStr),
+    Ident(Ident),
+    Int(LitInt),
+}
+
+impl CfgValue {
+    pub(crate) fn to_lit_str(&self) -> LitStr {
+        match self {
+            CfgValue::Str(lit) => lit.clone(),
+            CfgValue::Ident(ident) => LitStr::new(&ident.unraw().to_string(), ident.span()),
+            CfgValue::Int(lit) => LitStr::new(lit.base10_digits(), lit.span()),
+        }
+    }
+}
+
+impl Parse for CfgValue {
+    fn parse(input: ParseStream) -> ParseResult<Self> {
+        if input.peek(LitStr) {
+            Ok(CfgValue::Str(input.parse()?))
+        } else if input.peek(LitInt) {
+            let lit: LitInt = input.parse()?;
+            if !lit.suffix().is_empty() {
+                return Err(syn::Error::new(
+                    lit.span(),
+                    format!(
+                        "cfg values can't have a type suffix; use `{}` instead",
+                        lit.base10_digits()
+                    ),
+                ));
+            }
+            Ok(CfgValue::Int(lit))
+        } else if input.peek(Lit) {
+            let lit: Lit = input.parse()?;
+            Err(syn::Error::new(
+                lit.span(),
+                "this literal can't be used as a cfg value; use a string, an identifier or an integer",
+            ))
+        } else if input.peek(Ident::peek_any) {
+            Ok(CfgValue::Ident(input.call(Ident::parse_any)?))
+        } else {
+            Err(syn::Error::new(
+                input.span(),
+                "expected a cfg value (a string, an identifier or an integer)",
+            ))
+        }
+    }
+}
+
+/// A comparison operator, used for ordered conditions such as feature ladders
+#[derive(Clone, Copy)]
+pub(crate) enum CmpOp {
+    Ge,
+    Gt,
+    Le,
+    Lt,
+}
+
+impl CmpOp {
+    fn peek(input: &ParseStream) -> bool {
+        input.peek(Token![>=])
+            || input.peek(Token![<=])
+            || input.peek(Token![>])
+            || input.peek(Token![<])
+    }
+    /// check if the comparison holds, given how the left hand side orders relative to the right
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            CmpOp::Ge => ordering != Ordering::Less,
+            CmpOp::Gt => ordering == Ordering::Greater,
+            CmpOp::Le => ordering != Ordering::Greater,
+            CmpOp::Lt => ordering == Ordering::Less,
+        }
+    }
+}
+
+impl Parse for CmpOp {
+    fn parse(input: ParseStream) -> ParseResult<Self> {
+        if input.peek(Token![>=]) {
+            input.parse::<Token![>=]>()?;
+            Ok(CmpOp::Ge)
+        } else if input.peek(Token![<=]) {
+            input.parse::<Token![<=]>()?;
+            Ok(CmpOp::Le)
+        } else if input.peek(Token![>]) {
+            input.parse::<Token![>]>()?;
+            Ok(CmpOp::Gt)
+        } else {
+            input.parse::<Token![<]>()?;
+            Ok(CmpOp::Lt)
+        }
+    }
+}
+
+/// A `major.minor.patch` compiler version
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct RustcVersion([u64; 3]);
+
+impl RustcVersion {
+    /// parse `1.70` or `1.70.0`. a missing patch version is `0`
+    fn from_str(version: &str) -> Option<Self> {
+        let mut parts = version.split('.').map(|part| part.parse::<u64>().ok());
+        let major = parts.next()??;
+        let minor = parts.next()??;
+        let patch = parts.next().unwrap_or(Some(0))?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(RustcVersion([major, minor, patch]))
+    }
+    /// the version of the compiler, as detected by the build script
+    fn current() -> Option<Self> {
+        option_env!("PRAGMA_RUSTC_VERSION").and_then(Self::from_str)
+    }
+}
+
 /// parse condition expressions
 ///
 /// Grammar:
 /// ```text
-/// Condition := OrExpr
-/// OrExpr    := AndExpr ('or' AndExpr)*
-/// AndExpr   := Primary ('and' Primary)*
-/// Primary   := KeyVal | Key | Paren | NotExpr
+/// Condition := Implies
+/// Implies   := OrExpr ('implies' Implies)?
+/// OrExpr    := XorExpr (('or' | '||') XorExpr)*
+/// XorExpr   := AndExpr (('xor' | '^') AndExpr)*
+/// AndExpr   := Primary (('and' | '&&') Primary)*
+/// Primary   := KeyVal | NotEq | In | Glob | Rustc | Compare | Key | Paren | NotExpr
+///            | AllExpr | AnyExpr | OneOf | CfgExpr | Target | Env | Dep | Named | Call
 ///
-/// KeyVal    := Ident '=' LitStr
-/// Key       := Ident
+/// KeyVal    := Ident '=' Value
+/// NotEq     := Ident '!=' Value
+/// In        := Ident 'in' '[' (Value (',' Value)* ','?)? ']'
+/// Glob      := Ident '~' LitStr
+/// Compare   := Ident ('>=' | '>' | '<=' | '<') Value
+/// Rustc     := 'rustc' ('>=' | '>' | '<=' | '<') LitStr
+/// Env       := 'env' LitStr ('=' Value)?
+/// Dep       := 'dep' LitStr                     (a feature or an optional dependency in `Cargo.toml`)
+/// Kconfig   := Ident (('=' | '!=') Value)?       (for `CONFIG_` symbols, with a `.config` file)
+/// Named     := '@' Ident
+/// Call      := Ident '(' (Tokens (',' Tokens)* ','?)? ')'
+/// Key       := Ident                            (or an alias from `[package.metadata.pragma.aliases]`)
+/// Value     := LitStr | Ident | LitInt
 /// Paren     := '(' Condition ')'
-/// NotExpr   := 'not' '(' Condition ')'
+/// NotExpr   := ('not' | '!') Primary
+/// AllExpr   := 'all' '(' (Condition (',' Condition)* ','?)? ')'
+/// AnyExpr   := 'any' '(' (Condition (',' Condition)* ','?)? ')'
+/// OneOf     := ('exactly_one_of' | 'at_most_one_of') '(' (Condition (',' Condition)* ','?)? ')'
+/// CfgExpr   := 'cfg' '(' Condition ')'
+/// Target    := 'target' LitStr
 /// ```
 pub(crate) fn parse_condition(input: &ParseStream) -> ParseResult<ConditionExpr> {
-    parse_or_expr(input)
+    parse_implies_expr(input)
+}
+
+pub(crate) fn parse_implies_expr(input: &ParseStream) -> ParseResult<ConditionExpr> {
+    let expr = parse_or_expr(input)?;
+    // `implies` is right associative: `a implies b implies c` is `a implies (b implies c)`
+    if peek_keyword(input, "implies") {
+        input.parse::<Ident>()?;
+        let rhs = parse_implies_expr(input)?;
+        return Ok(ConditionExpr::Implies(Box::new(expr), Box::new(rhs)));
+    }
+    Ok(expr)
 }
 
 pub(crate) fn parse_or_expr(input: &ParseStream) -> ParseResult<ConditionExpr> {
-    let mut expr = parse_and_expr(input)?;
-    loop {
-        // look ahead to see if the next ident is "or"
-        if input.peek(Ident) {
-            let ident_peek = input.fork().parse::<Ident>()?;
-            if ident_peek == "or" {
-                // consume `or` and parse the next AndExpr
-                input.parse::<Ident>()?; // actually consume "or"
-                let rhs = parse_and_expr(input)?;
-                expr = match expr {
-                    ConditionExpr::Any(mut v) => {
-                        v.push(rhs);
-                        ConditionExpr::Any(v)
-                    }
-                    _ => ConditionExpr::Any(vec![expr, rhs]),
-                };
-            } else {
-                // not "or", so we're done with OrExpr parsing
-                break;
+    let mut expr = parse_xor_expr(input)?;
+    // look ahead to see if the next token is `or` or `||`
+    while parse_operator::<Token![||]>(input, "or")? {
+        let rhs = parse_xor_expr(input)?;
+        expr = match expr {
+            ConditionExpr::Any(mut v) => {
+                v.push(rhs);
+                ConditionExpr::Any(v)
             }
-        } else {
-            break;
-        }
+            _ => ConditionExpr::Any(vec![expr, rhs]),
+        };
+    }
+    // not `or`, so we're done with OrExpr parsing
+    Ok(expr)
+}
+
+pub(crate) fn parse_xor_expr(input: &ParseStream) -> ParseResult<ConditionExpr> {
+    let mut expr = parse_and_expr(input)?;
+    while parse_operator::<Token![^]>(input, "xor")? {
+        let rhs = parse_and_expr(input)?;
+        expr = ConditionExpr::Xor(Box::new(expr), Box::new(rhs));
     }
     Ok(expr)
 }
 
 pub(crate) fn parse_and_expr(input: &ParseStream) -> ParseResult<ConditionExpr> {
     let mut expr = parse_primary(input)?;
-    loop {
-        // look ahead to see if the next ident is "and"
-        if input.peek(Ident) {
-            let ident_peek = input.fork().parse::<Ident>()?;
-            if ident_peek == "and" {
-                // consume `and` and parse the next Primary
-                input.parse::<Ident>()?; // consume "and"
-                let rhs = parse_primary(input)?;
-                expr = match expr {
-                    ConditionExpr::All(mut v) => {
-                        v.push(rhs);
-                        ConditionExpr::All(v)
-                    }
-                    _ => ConditionExpr::All(vec![expr, rhs]),
-                };
-            } else {
-                // not "and", so we're done with AndExpr parsing.
-                // this could be "or" or something else that belongs to a higher level.
-                break;
+    // look ahead to see if the next token is `and` or `&&`
+    while parse_operator::<Token![&&]>(input, "and")? {
+        let rhs = parse_primary(input)?;
+        expr = match expr {
+            ConditionExpr::All(mut v) => {
+                v.push(rhs);
+                ConditionExpr::All(v)
             }
-        } else {
-            break;
-        }
+            _ => ConditionExpr::All(vec![expr, rhs]),
+        };
     }
+    // not `and`, so we're done with AndExpr parsing.
+    // this could be `or` or something else that belongs to a higher level.
     Ok(expr)
 }
 
+/// check if the next token is the given contextual keyword, without consuming it
+pub(crate) fn peek_keyword(input: &ParseStream, keyword: &str) -> bool {
+    input
+        .cursor()
+        .ident()
+        .is_some_and(|(ident, _)| ident == keyword)
+}
+
+/// consume a binary operator, spelled either as the `word` keyword or as the C-style token `T`.
+/// returns `false` (consuming nothing) if the next token is neither
+fn parse_operator<T: Parse>(input: &ParseStream, word: &str) -> ParseResult<bool> {
+    if peek_keyword(input, word) {
+        input.parse::<Ident>()?;
+        Ok(true)
+    } else if input.fork().parse::<T>().is_ok() {
+        input.parse::<T>()?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// consume the invisible group that `macro_rules!` wraps around a forwarded fragment (such as
+/// `$c:expr` or `$c:meta`) and return its contents. returns `None` if the next token isn't such a
+/// group
+pub(crate) fn parse_none_group(input: &ParseStream) -> ParseResult<Option<TokenStream>> {
+    input.step(|cursor| match cursor.group(Delimiter::None) {
+        Some((inner, _, rest)) => Ok((Some(inner.token_stream()), rest)),
+        None => Ok((None, *cursor)),
+    })
+}
+
 pub(crate) fn parse_primary(input: &ParseStream) -> ParseResult<ConditionExpr> {
+    // a forwarded fragment is a condition of its own, so `not $c` negates all of `$c`. a single
+    // token (such as a forwarded key that is followed by `= value`) is left for the cases below
+    let single_token = input
+        .cursor()
+        .group(Delimiter::None)
+        .map(|(inner, _, _)| inner.token_tree().is_some_and(|(_, rest)| rest.eof()));
+    if single_token == Some(false) {
+        if let Some(tokens) = parse_none_group(input)? {
+            return Parser::parse2(|input: ParseStream| parse_condition(&input), tokens);
+        }
+    }
+
+    if input.peek(Token![!]) {
+        // parse `!x`
+        input.parse::<Token![!]>()?;
+        let inner = parse_primary(input)?;
+        return Ok(ConditionExpr::Not(Box::new(inner)));
+    }
+
+    if input.peek(Token![@]) {
+        // parse `@name`
+        input.parse::<Token![@]>()?;
+        return Ok(ConditionExpr::Named(input.parse()?));
+    }
+
     if input.peek(Ident) {
         // check if it's `not(...)` or a key/key=val
         let ident: Ident = input.parse()?;
+        if ident.to_string().starts_with("CONFIG_") {
+            // Kconfig symbols are only special if a `.config` file is configured
+            let path = kconfig::config_path().map_err(|msg| syn::Error::new(ident.span(), msg))?;
+            if let Some(path) = path {
+                return kconfig_condition(&ident, path, input);
+            }
+        }
         if ident == "not" {
-            // parse 'not(...)'
+            // parse `not(...)` or `not x`
+            let inner = parse_primary(input)?;
+            return Ok(ConditionExpr::Not(Box::new(inner)));
+        } else if (ident == "all" || ident == "any") && input.peek(syn::token::Paren) {
+            // parse rustc style `all(...)` and `any(...)`
             let content;
             let _paren = syn::parenthesized!(content in input);
-            let inner = parse_condition(&&content)?;
-            return Ok(ConditionExpr::Not(Box::new(inner)));
+            let exprs =
+                Punctuated::<ConditionExpr, Token![,]>::parse_terminated_with(&content, |input| {
+                    parse_condition(&input)
+                })?;
+            let exprs = exprs.into_iter().collect();
+            if ident == "all" {
+                return Ok(ConditionExpr::All(exprs));
+            } else {
+                return Ok(ConditionExpr::Any(exprs));
+            }
+        } else if (ident == "exactly_one_of" || ident == "at_most_one_of")
+            && input.peek(syn::token::Paren)
+        {
+            // parse `exactly_one_of(...)` and `at_most_one_of(...)`
+            let content;
+            let _paren = syn::parenthesized!(content in input);
+            let exprs =
+                Punctuated::<ConditionExpr, Token![,]>::parse_terminated_with(&content, |input| {
+                    parse_condition(&input)
+                })?;
+            let exprs = exprs.into_iter().collect();
+            if ident == "exactly_one_of" {
+                return Ok(ConditionExpr::ExactlyOne(exprs));
+            } else {
+                return Ok(ConditionExpr::AtMostOne(exprs));
+            }
+        } else if ident == "target" && input.peek(LitStr) {
+            // parse `target "arch-vendor-os-env"`
+            let triple: LitStr = input.parse()?;
+            let pairs = known::split_triple(&triple.value())
+                .map_err(|msg| syn::Error::new(triple.span(), msg))?;
+            let exprs = pairs
+                .into_iter()
+                .map(|(key, value)| {
+                    ConditionExpr::KeyVal(
+                        Ident::new(key, triple.span()),
+                        CfgValue::Str(LitStr::new(value, triple.span())),
+                    )
+                })
+                .collect();
+            return Ok(ConditionExpr::All(exprs));
+        } else if ident == "env" && input.peek(LitStr) {
+            // parse `env "NAME"` or `env "NAME" = value`
+            let name: LitStr = input.parse()?;
+            let value = if input.peek(Token![=]) {
+                input.parse::<Token![=]>()?;
+                Some(input.parse()?)
+            } else {
+                None
+            };
+            return Ok(ConditionExpr::Env(name, value));
+        } else if ident == "dep" && input.peek(LitStr) {
+            // parse `dep "name"`
+            return dep_condition(input.parse()?);
+        } else if ident == "cfg" && input.peek(syn::token::Paren) {
+            // parse an explicit `cfg(...)` wrapper
+            let content;
+            let _paren = syn::parenthesized!(content in input);
+            return parse_condition(&&content);
+        } else if input.peek(syn::token::Paren) {
+            // parse a call to a condition template: `name(args..)`
+            let content;
+            let _paren = syn::parenthesized!(content in input);
+            let args = template::parse_call_args(&content)?;
+            return Ok(ConditionExpr::Call(ident, args));
         } else {
-            // it's a key or key=val
+            // it's a key, key=val, key!=val, key in [..], key ~ "glob" or a comparison
             if input.peek(Token![=]) {
                 input.parse::<Token![=]>()?;
-                let val: LitStr = input.parse()?;
+                let val: CfgValue = input.parse()?;
                 return Ok(ConditionExpr::KeyVal(ident, val));
+            } else if input.peek(Token![!=]) {
+                input.parse::<Token![!=]>()?;
+                let val: CfgValue = input.parse()?;
+                return Ok(ConditionExpr::NotEq(ident, val));
+            } else if ident == "rustc" && CmpOp::peek(input) {
+                let op: CmpOp = input.parse()?;
+                let version: LitStr = input.parse()?;
+                let version = RustcVersion::from_str(&version.value()).ok_or_else(|| {
+                    syn::Error::new(
+                        version.span(),
+                        "expected a compiler version such as \"1.70\" or \"1.70.0\"",
+                    )
+                })?;
+                if RustcVersion::current().is_none() {
+                    return Err(syn::Error::new(
+                        ident.span(),
+                        "the version of the compiler couldn't be detected when `pragma` was built",
+                    ));
+                }
+                return Ok(ConditionExpr::RustcVersion(op, version));
+            } else if CmpOp::peek(input) {
+                let op: CmpOp = input.parse()?;
+                let val: CfgValue = input.parse()?;
+                return ladder_condition(ident, op, val);
+            } else if input.peek(Token![~]) {
+                input.parse::<Token![~]>()?;
+                let pattern: LitStr = input.parse()?;
+                let values = known::values(&ident.to_string()).ok_or_else(|| {
+                    syn::Error::new(
+                        ident.span(),
+                        format!(
+                            "`{}` has no table of known values; `~` only works with \
+                            `target_arch`, `target_vendor`, `target_os`, `target_env` and `target_family`",
+                            ident
+                        ),
+                    )
+                })?;
+                if !values
+                    .iter()
+                    .any(|value| known::glob_match(&pattern.value(), value))
+                {
+                    return Err(syn::Error::new(
+                        pattern.span(),
+                        format!("no known value of `{}` matches this pattern", ident),
+                    ));
+                }
+                return Ok(ConditionExpr::Glob(ident, pattern));
+            } else if input.peek(Token![in]) {
+                input.parse::<Token![in]>()?;
+                let content;
+                let _bracket = syn::bracketed!(content in input);
+                let vals = Punctuated::<CfgValue, Token![,]>::parse_terminated(&content)?;
+                return Ok(ConditionExpr::In(ident, vals.into_iter().collect()));
             } else {
+                if let Some(alias) = alias_condition(&ident)? {
+                    return Ok(alias);
+                }
                 return Ok(ConditionExpr::Key(ident));
             }
         }
@@ -120,10 +515,302 @@ pub(crate) fn parse_primary(input: &ParseStream) -> ParseResult<ConditionExpr> {
 
     Err(syn::Error::new(
         input.span(),
-        "expected condition (key, key=val, not(...), or (...))",
+        "expected condition (key, key=val, key!=val, key in [..], key ~ \"glob\", not(...), !x, or (...))",
     ))
 }
 
+/// evaluate `CONFIG_FOO`, `CONFIG_FOO = value` or `CONFIG_FOO != value` against a `.config` file
+///
+/// A symbol is enabled if it is set to anything other than `n`.
+fn kconfig_condition(
+    symbol: &Ident,
+    path: PathBuf,
+    input: &ParseStream,
+) -> ParseResult<ConditionExpr> {
+    let symbols = kconfig::load(&path).map_err(|msg| syn::Error::new(symbol.span(), msg))?;
+    let actual = symbols.get(&symbol.to_string());
+    let holds = if input.peek(Token![=]) {
+        input.parse::<Token![=]>()?;
+        let expected: CfgValue = input.parse()?;
+        actual.is_some_and(|actual| *actual == expected.to_lit_str().value())
+    } else if input.peek(Token![!=]) {
+        input.parse::<Token![!=]>()?;
+        let expected: CfgValue = input.parse()?;
+        actual.map_or(true, |actual| *actual != expected.to_lit_str().value())
+    } else {
+        actual.is_some_and(|actual| actual != "n")
+    };
+    Ok(ConditionExpr::Kconfig {
+        holds,
+        path: path.to_string_lossy().into_owned(),
+    })
+}
+
+thread_local! {
+    /// the aliases that are currently being expanded, to detect cycles
+    static EXPANDING_ALIASES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// look up `name` in `[package.metadata.pragma.aliases]`, and parse the condition it stands for
+fn alias_condition(name: &Ident) -> ParseResult<Option<ConditionExpr>> {
+    let key = name.to_string();
+    if key.starts_with("r#") {
+        // raw identifiers always refer to cfg options
+        return Ok(None);
+    }
+    let manifest = match manifest::load_if_present() {
+        Ok(Some(manifest)) => manifest,
+        // without a manifest there are no aliases
+        Ok(None) => return Ok(None),
+        Err(msg) => return Err(syn::Error::new(name.span(), msg)),
+    };
+    let definition = match manifest::get(
+        &manifest,
+        &["package", "metadata", "pragma", "aliases", &key],
+    ) {
+        Some(definition) => definition.as_str().ok_or_else(|| {
+            syn::Error::new(
+                name.span(),
+                format!("the alias `{}` must be a string holding a condition", key),
+            )
+        })?,
+        None => {
+            // inside an alias, a bare key is most likely a misspelled alias unless it is a known
+            // cfg option
+            let in_alias = EXPANDING_ALIASES.with(|expanding| !expanding.borrow().is_empty());
+            if in_alias
+                && !known::BARE_CFGS.contains(&key.as_str())
+                && !shorthand::is_shorthand(&key)
+                && !checked_cfgs(&manifest).any(|cfg| cfg == key)
+            {
+                return Err(syn::Error::new(
+                    name.span(),
+                    format!(
+                        "no alias named `{0}` is defined; use `r#{0}` for a custom cfg option",
+                        key
+                    ),
+                ));
+            }
+            return Ok(None);
+        }
+    };
+
+    let cycle = EXPANDING_ALIASES.with(|expanding| {
+        let mut expanding = expanding.borrow_mut();
+        let cycle = expanding
+            .iter()
+            .position(|alias| *alias == key)
+            .map(|start| {
+                let mut cycle = expanding[start..].to_vec();
+                cycle.push(key.clone());
+                cycle.join("` -> `")
+            });
+        expanding.push(key.clone());
+        cycle
+    });
+    let result = match cycle {
+        Some(cycle) => Err(syn::Error::new(
+            name.span(),
+            format!(
+                "in the alias `{}`: the alias refers to itself (`{}`)",
+                key, cycle
+            ),
+        )),
+        None => {
+            // point all the diagnostics for the alias' tokens at its use
+            definition
+                .parse::<proc_macro2::TokenStream>()
+                .map_err(|e| syn::Error::new(name.span(), e.to_string()))
+                .and_then(|tokens| {
+                    Parser::parse2(
+                        |input: ParseStream| parse_condition(&input),
+                        respan(tokens, name.span()),
+                    )
+                })
+                .map_err(|e| {
+                    // don't nest the context of the aliases this one refers to
+                    if e.to_string().starts_with("in the alias") {
+                        e
+                    } else {
+                        syn::Error::new(
+                            name.span(),
+                            format!("in the alias `{}` = {:?}: {}", key, definition, e),
+                        )
+                    }
+                })
+        }
+    };
+    EXPANDING_ALIASES.with(|expanding| expanding.borrow_mut().pop());
+    result.map(Some)
+}
+
+/// the names of the custom cfg options declared in `[lints.rust.unexpected_cfgs]`, for example
+/// `tokio_unstable` in `check-cfg = ['cfg(tokio_unstable)']`
+fn checked_cfgs(manifest: &manifest::Table) -> impl Iterator<Item = &str> {
+    let checked = match manifest::get(manifest, &["lints", "rust", "unexpected_cfgs", "check-cfg"])
+    {
+        Some(manifest::Value::Array(checked)) => &checked[..],
+        _ => &[],
+    };
+    checked.iter().filter_map(|cfg| {
+        let cfg = cfg.as_str()?.trim().strip_prefix("cfg(")?;
+        let end = cfg.find([',', ')'])?;
+        Some(cfg[..end].trim())
+    })
+}
+
+/// give every token the span `span`, so that diagnostics point at the given location
+pub(crate) fn respan(tokens: proc_macro2::TokenStream, span: Span) -> proc_macro2::TokenStream {
+    tokens
+        .into_iter()
+        .map(|mut token| {
+            if let TokenTree::Group(group) = &token {
+                let mut new = Group::new(group.delimiter(), respan(group.stream(), span));
+                new.set_span(span);
+                token = TokenTree::Group(new);
+            } else {
+                token.set_span(span);
+            }
+            token
+        })
+        .collect()
+}
+
+/// expand `dep "name"` into `feature = "name"`, after checking that the manifest declares a
+/// feature or an optional dependency called `name`
+fn dep_condition(name: LitStr) -> ParseResult<ConditionExpr> {
+    let manifest = manifest::load().map_err(|msg| syn::Error::new(name.span(), msg))?;
+    let value = name.value();
+    let feature = ConditionExpr::KeyVal(
+        Ident::new("feature", name.span()),
+        CfgValue::Str(name.clone()),
+    );
+    let features = manifest.get("features").and_then(manifest::Value::as_table);
+    if features.is_some_and(|features| features.contains_key(&value)) {
+        return Ok(feature);
+    }
+    let dependencies = manifest::dependencies(&manifest);
+    let optional = dependencies
+        .iter()
+        .any(|(dep, optional)| *dep == value && *optional);
+    if !optional {
+        let msg = if dependencies.iter().any(|(dep, _)| *dep == value) {
+            format!(
+                "the dependency `{}` is not optional, so there is no feature to check",
+                value
+            )
+        } else {
+            format!(
+                "no feature or optional dependency named `{}` in `Cargo.toml`",
+                value
+            )
+        };
+        return Err(syn::Error::new(name.span(), msg));
+    }
+    // an optional dependency only gets an implicit feature if no feature refers to it as
+    // `dep:name`
+    let explicit = format!("dep:{}", value);
+    let enabled_by: Vec<String> = features
+        .into_iter()
+        .flat_map(|features| features.iter())
+        .filter(|(_, enables)| match enables {
+            manifest::Value::Array(enables) => enables
+                .iter()
+                .any(|enable| enable.as_str() == Some(&explicit)),
+            _ => false,
+        })
+        .map(|(feature, _)| format!("`{}`", feature))
+        .collect();
+    if !enabled_by.is_empty() {
+        return Err(syn::Error::new(
+            name.span(),
+            format!(
+                "the optional dependency `{}` is only enabled through `{}`, so there is no `{}` \
+                 feature; check for a feature that enables it instead ({})",
+                value,
+                explicit,
+                value,
+                enabled_by.join(", ")
+            ),
+        ));
+    }
+    Ok(feature)
+}
+
+/// expand a comparison on a feature ladder declared in `[package.metadata.pragma.ladders]`
+///
+/// A ladder is a list of cumulative features, ordered from the lowest to the highest level, so
+/// `ladder >= "rung"` holds if `rung` or any of the features above it is enabled.
+fn ladder_condition(ladder: Ident, op: CmpOp, rung: CfgValue) -> ParseResult<ConditionExpr> {
+    let rung = rung.to_lit_str();
+    let manifest = manifest::load().map_err(|msg| syn::Error::new(ladder.span(), msg))?;
+    let name = ladder.to_string();
+    let rungs = match manifest::get(
+        &manifest,
+        &["package", "metadata", "pragma", "ladders", &name],
+    ) {
+        Some(manifest::Value::Array(rungs)) => rungs
+            .iter()
+            .map(|rung| rung.as_str().map(str::to_owned))
+            .collect::<Option<Vec<String>>>()
+            .ok_or_else(|| {
+                syn::Error::new(
+                    ladder.span(),
+                    format!("the ladder `{}` must be an array of feature names", name),
+                )
+            })?,
+        _ => {
+            return Err(syn::Error::new(
+                ladder.span(),
+                format!(
+                    "no ladder named `{}` in `[package.metadata.pragma.ladders]`",
+                    name
+                ),
+            ))
+        }
+    };
+    let position = rungs
+        .iter()
+        .position(|r| *r == rung.value())
+        .ok_or_else(|| {
+            syn::Error::new(
+                rung.span(),
+                format!(
+                    "`{}` is not a rung of the ladder `{}` (expected one of {})",
+                    rung.value(),
+                    name,
+                    rungs
+                        .iter()
+                        .map(|r| format!("`{}`", r))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            )
+        })?;
+    // the features at or above the level the comparison is relative to
+    let (from, negate) = match op {
+        CmpOp::Ge => (position, false),
+        CmpOp::Gt => (position + 1, false),
+        CmpOp::Lt => (position, true),
+        CmpOp::Le => (position + 1, true),
+    };
+    let above = rungs[from..]
+        .iter()
+        .map(|feature| {
+            ConditionExpr::KeyVal(
+                Ident::new("feature", ladder.span()),
+                CfgValue::Str(LitStr::new(feature, rung.span())),
+            )
+        })
+        .collect();
+    let above = ConditionExpr::Any(above);
+    if negate {
+        Ok(ConditionExpr::Not(Box::new(above)))
+    } else {
+        Ok(above)
+    }
+}
+
 pub(crate) fn condition_to_cfg(expr: &ConditionExpr) -> proc_macro2::TokenStream {
     match expr {
         ConditionExpr::All(exprs) => {
@@ -138,11 +825,215 @@ pub(crate) fn condition_to_cfg(expr: &ConditionExpr) -> proc_macro2::TokenStream
             let inner = condition_to_cfg(e);
             quote! { not(#inner) }
         }
+        ConditionExpr::Xor(lhs, rhs) => {
+            let (lhs, rhs) = (condition_to_cfg(lhs), condition_to_cfg(rhs));
+            quote! { any(all(#lhs, not(#rhs)), all(not(#lhs), #rhs)) }
+        }
+        ConditionExpr::Implies(lhs, rhs) => {
+            let (lhs, rhs) = (condition_to_cfg(lhs), condition_to_cfg(rhs));
+            quote! { any(not(#lhs), #rhs) }
+        }
+        ConditionExpr::ExactlyOne(exprs) => {
+            // one of them holds, and none of the others do
+            let exprs: Vec<_> = exprs.iter().map(condition_to_cfg).collect();
+            let arms = (0..exprs.len()).map(|i| {
+                let others = exprs
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, e)| e);
+                let this = &exprs[i];
+                quote! { all(#this, #(not(#others)),*) }
+            });
+            quote! { any(#(#arms),*) }
+        }
+        ConditionExpr::AtMostOne(exprs) => {
+            // no two of them hold at the same time
+            let exprs: Vec<_> = exprs.iter().map(condition_to_cfg).collect();
+            let pairs = (0..exprs.len()).flat_map(|i| {
+                let exprs = &exprs;
+                (i + 1..exprs.len()).map(move |j| {
+                    let (a, b) = (&exprs[i], &exprs[j]);
+                    quote! { all(#a, #b) }
+                })
+            });
+            quote! { not(any(#(#pairs),*)) }
+        }
         ConditionExpr::KeyVal(ident, val) => {
+            let val = val.to_lit_str();
             quote! { #ident = #val }
         }
-        ConditionExpr::Key(ident) => {
-            quote! { #ident }
+        ConditionExpr::NotEq(ident, val) => {
+            let val = val.to_lit_str();
+            quote! { not(#ident = #val) }
+        }
+        ConditionExpr::In(ident, vals) => {
+            let keys = std::iter::repeat(ident);
+            let vals = vals.iter().map(CfgValue::to_lit_str);
+            quote! { any(#(#keys = #vals),*) }
+        }
+        ConditionExpr::Glob(ident, pattern) => {
+            let values = known::values(&ident.to_string()).unwrap_or_default();
+            let vals = values
+                .iter()
+                .filter(|value| known::glob_match(&pattern.value(), value))
+                .map(|value| LitStr::new(value, pattern.span()));
+            let keys = std::iter::repeat(ident);
+            quote! { any(#(#keys = #vals),*) }
+        }
+        ConditionExpr::RustcVersion(op, version) => {
+            // resolve to an always true or always false predicate
+            let holds =
+                RustcVersion::current().is_some_and(|current| op.holds(current.cmp(version)));
+            if holds {
+                quote! { all() }
+            } else {
+                quote! { any() }
+            }
+        }
+        ConditionExpr::Env(name, value) => {
+            let holds = match (std::env::var(name.value()), value) {
+                (Ok(actual), Some(expected)) => actual == expected.to_lit_str().value(),
+                (Ok(_), None) => true,
+                (Err(_), _) => false,
+            };
+            if holds {
+                quote! { all() }
+            } else {
+                quote! { any() }
+            }
+        }
+        ConditionExpr::Kconfig { holds: true, .. } => quote! { all() },
+        ConditionExpr::Kconfig { holds: false, .. } => quote! { any() },
+        ConditionExpr::Named(_) | ConditionExpr::Call(..) => {
+            unreachable!("named conditions and templates are substituted before expansion")
+        }
+        ConditionExpr::Key(ident) => shorthand::expand_key(ident),
+    }
+}
+
+/// Generate the items that make cargo rebuild the crate when something the condition was
+/// evaluated against at expansion time changes.
+pub(crate) fn condition_dependencies(expr: &ConditionExpr) -> proc_macro2::TokenStream {
+    let mut deps = proc_macro2::TokenStream::new();
+    expr.visit(&mut |expr| {
+        match expr {
+            ConditionExpr::Env(name, _) => {
+                // rustc records the variables read by `option_env!`, and cargo tracks them
+                deps.extend(quote! {
+                    const _: ::core::option::Option<&str> = ::core::option_env!(#name);
+                });
+            }
+            ConditionExpr::Kconfig { path, .. } => {
+                // same for the files read by `include_bytes!`, and the path can be overridden by
+                // `PRAGMA_KCONFIG`
+                deps.extend(quote! {
+                    const _: &[u8] = ::core::include_bytes!(#path);
+                    const _: ::core::option::Option<&str> = ::core::option_env!("PRAGMA_KCONFIG");
+                });
+            }
+            _ => {}
         }
+    });
+    if manifest::take_consulted() {
+        // aliases, ladders, `dep` and the path of the `.config` file come from the manifest
+        deps.extend(quote! {
+            const _: &[u8] = ::core::include_bytes!(::core::concat!(
+                ::core::env!("CARGO_MANIFEST_DIR"),
+                "/Cargo.toml"
+            ));
+        });
+    }
+    deps
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::manifest};
+
+    const ALIASES: &str = r#"
+[package.metadata.pragma.aliases]
+desktop = 'linux or windows'
+wide_desktop = "desktop and wdie"
+ping = "pong"
+pong = "unix and ping"
+checked = "desktop and tokio_unstable and not(debug)"
+
+[lints.rust]
+unexpected_cfgs = { level = "warn", check-cfg = ['cfg(tokio_unstable)'] }
+"#;
+
+    fn cfg(condition: &str) -> ParseResult<String> {
+        let condition = Parser::parse2(
+            |input: ParseStream| parse_condition(&input),
+            condition.parse().unwrap(),
+        )?;
+        Ok(condition_to_cfg(&condition).to_string())
+    }
+
+    #[test]
+    fn aliases_without_manifest() {
+        manifest::set(None);
+        assert_eq!(cfg("desktop").unwrap(), "desktop");
+    }
+
+    #[test]
+    fn kconfig_without_manifest() {
+        manifest::set(None);
+        assert_eq!(cfg("CONFIG_FOO").unwrap(), "CONFIG_FOO");
+    }
+
+    #[test]
+    fn alias_cycle() {
+        manifest::set(Some(ALIASES));
+        assert_eq!(
+            cfg("ping").unwrap_err().to_string(),
+            "in the alias `ping`: the alias refers to itself (`ping` -> `pong` -> `ping`)"
+        );
+    }
+
+    #[test]
+    fn undefined_alias() {
+        manifest::set(Some(ALIASES));
+        assert_eq!(
+            cfg("wide_desktop").unwrap_err().to_string(),
+            "in the alias `wide_desktop` = \"desktop and wdie\": no alias named `wdie` is \
+             defined; use `r#wdie` for a custom cfg option"
+        );
+        // outside of an alias, bare keys are cfg options
+        assert_eq!(cfg("wdie").unwrap(), "wdie");
+        // built-in and declared cfg options are fine inside an alias
+        assert!(cfg("checked").is_ok());
+    }
+
+    const DEPENDENCIES: &str = r#"
+[dependencies]
+syn = "1"
+serde = { version = "1", optional = true }
+quote = { version = "1", optional = true }
+
+[features]
+std = []
+quoting = ["dep:quote"]
+"#;
+
+    #[test]
+    fn dependency_features() {
+        manifest::set(Some(DEPENDENCIES));
+        assert_eq!(cfg(r#"dep "std""#).unwrap(), r#"feature = "std""#);
+        assert_eq!(cfg(r#"dep "serde""#).unwrap(), r#"feature = "serde""#);
+        assert_eq!(
+            cfg(r#"dep "sedre""#).unwrap_err().to_string(),
+            "no feature or optional dependency named `sedre` in `Cargo.toml`"
+        );
+        assert_eq!(
+            cfg(r#"dep "syn""#).unwrap_err().to_string(),
+            "the dependency `syn` is not optional, so there is no feature to check"
+        );
+        assert_eq!(
+            cfg(r#"dep "quote""#).unwrap_err().to_string(),
+            "the optional dependency `quote` is only enabled through `dep:quote`, so there is no \
+             `quote` feature; check for a feature that enables it instead (`quoting`)"
+        );
     }
 }
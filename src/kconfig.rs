@@ -0,0 +1,99 @@
+//! Reading Kconfig `.config` files
+//!
+//! The path of the file is taken from the `PRAGMA_KCONFIG` environment variable, or from the
+//! `kconfig` key of `[package.metadata.pragma]`. Relative paths are relative to the directory of
+//! the crate's manifest.
+
+use {
+    crate::manifest,
+    std::{cell::RefCell, collections::BTreeMap, env, fs, path::PathBuf, rc::Rc},
+};
+
+type Symbols = BTreeMap<String, String>;
+/// the symbols of a `.config` file, or why it couldn't be read
+type Loaded = Result<Rc<Symbols>, String>;
+
+thread_local! {
+    /// the `.config` file read during the current expansion, and its symbols
+    static CACHE: RefCell<Option<(PathBuf, Loaded)>> = const { RefCell::new(None) };
+}
+
+/// forget the cached `.config` file. This is called at the start of every expansion
+pub(crate) fn reset() {
+    CACHE.with(|cache| *cache.borrow_mut() = None);
+}
+
+/// the path of the configured `.config` file, if there is one
+pub(crate) fn config_path() -> Result<Option<PathBuf>, String> {
+    let path = match env::var_os("PRAGMA_KCONFIG") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let manifest = match manifest::load_if_present()? {
+                Some(manifest) => manifest,
+                None => return Ok(None),
+            };
+            match manifest::get(&manifest, &["package", "metadata", "pragma", "kconfig"]) {
+                Some(path) => PathBuf::from(path.as_str().ok_or(
+                    "`kconfig` in `[package.metadata.pragma]` must be the path of a `.config` file",
+                )?),
+                None => return Ok(None),
+            }
+        }
+    };
+    if path.is_absolute() {
+        return Ok(Some(path));
+    }
+    let dir = manifest::manifest_path()?;
+    Ok(Some(dir.parent().unwrap().join(path)))
+}
+
+/// read the symbols of a `.config` file. symbols that are explicitly not set have the value `n`,
+/// and string values are unquoted
+///
+/// The file is read at most once per expansion.
+pub(crate) fn load(path: &PathBuf) -> Loaded {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        match &*cache {
+            Some((cached, symbols)) if cached == path => symbols.clone(),
+            _ => {
+                let symbols = parse(path).map(Rc::new);
+                *cache = Some((path.clone(), symbols.clone()));
+                symbols
+            }
+        }
+    })
+}
+
+/// read and parse the `.config` file at `path`
+fn parse(path: &PathBuf) -> Result<Symbols, String> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read `{}`: {}", path.display(), e))?;
+    let mut symbols = BTreeMap::new();
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            // `# CONFIG_FOO is not set`
+            if let Some(symbol) = comment.trim().strip_suffix(" is not set") {
+                symbols.insert(symbol.trim().to_owned(), "n".to_owned());
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let (symbol, value) = line.split_once('=').ok_or_else(|| {
+            format!(
+                "failed to parse `{}`: expected `CONFIG_NAME=value` on line {}",
+                path.display(),
+                i + 1
+            )
+        })?;
+        let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+            None => value.to_owned(),
+        };
+        symbols.insert(symbol.trim().to_owned(), value);
+    }
+    Ok(symbols)
+}
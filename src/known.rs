@@ -0,0 +1,559 @@
+//! Tables of well-known cfg values
+
+/// well-known values of `target_arch`
+pub(crate) const TARGET_ARCH: &[&str] = &[
+    "aarch64",
+    "amdgpu",
+    "arm",
+    "arm64ec",
+    "avr",
+    "bpf",
+    "csky",
+    "hexagon",
+    "loongarch32",
+    "loongarch64",
+    "m68k",
+    "mips",
+    "mips32r6",
+    "mips64",
+    "mips64r6",
+    "msp430",
+    "nvptx64",
+    "powerpc",
+    "powerpc64",
+    "riscv32",
+    "riscv64",
+    "s390x",
+    "sparc",
+    "sparc64",
+    "wasm32",
+    "wasm64",
+    "x86",
+    "x86_64",
+    "xtensa",
+];
+
+/// well-known values of `target_vendor`
+pub(crate) const TARGET_VENDOR: &[&str] = &[
+    "amd",
+    "apple",
+    "espressif",
+    "fortanix",
+    "ibm",
+    "kmc",
+    "mti",
+    "nintendo",
+    "nvidia",
+    "openwrt",
+    "pc",
+    "risc0",
+    "sony",
+    "sun",
+    "unikraft",
+    "unknown",
+    "uwp",
+    "vex",
+    "win7",
+    "wrs",
+];
+
+/// well-known values of `target_os`
+pub(crate) const TARGET_OS: &[&str] = &[
+    "aix",
+    "amdhsa",
+    "android",
+    "cuda",
+    "cygwin",
+    "dragonfly",
+    "emscripten",
+    "espidf",
+    "freebsd",
+    "fuchsia",
+    "haiku",
+    "helenos",
+    "hermit",
+    "horizon",
+    "hurd",
+    "illumos",
+    "ios",
+    "l4re",
+    "linux",
+    "lynxos178",
+    "macos",
+    "managarm",
+    "motor",
+    "netbsd",
+    "none",
+    "nto",
+    "nuttx",
+    "openbsd",
+    "psp",
+    "psx",
+    "qurt",
+    "redox",
+    "rtems",
+    "solaris",
+    "solid_asp3",
+    "teeos",
+    "trusty",
+    "tvos",
+    "uefi",
+    "unknown",
+    "vexos",
+    "visionos",
+    "vita",
+    "vxworks",
+    "wasi",
+    "watchos",
+    "windows",
+    "xous",
+    "zkvm",
+];
+
+/// well-known values of `target_env`
+pub(crate) const TARGET_ENV: &[&str] = &[
+    "",
+    "gnu",
+    "macabi",
+    "mlibc",
+    "msvc",
+    "musl",
+    "newlib",
+    "nto70",
+    "nto71",
+    "nto71_iosock",
+    "nto80",
+    "ohos",
+    "p1",
+    "p2",
+    "p3",
+    "relibc",
+    "sgx",
+    "sim",
+    "uclibc",
+    "v5",
+];
+
+/// well-known values of `target_family`
+pub(crate) const TARGET_FAMILY: &[&str] = &["unix", "wasm", "windows"];
+
+/// the `target_feature`s of x86 and x86_64, which `is_x86_feature_detected!` can detect
+pub(crate) const X86_FEATURES: &[&str] = &[
+    "adx",
+    "aes",
+    "avx",
+    "avx2",
+    "avx512bf16",
+    "avx512bitalg",
+    "avx512bw",
+    "avx512cd",
+    "avx512dq",
+    "avx512f",
+    "avx512fp16",
+    "avx512ifma",
+    "avx512vbmi",
+    "avx512vbmi2",
+    "avx512vl",
+    "avx512vnni",
+    "avx512vp2intersect",
+    "avx512vpopcntdq",
+    "avxifma",
+    "avxneconvert",
+    "avxvnni",
+    "avxvnniint16",
+    "avxvnniint8",
+    "bmi1",
+    "bmi2",
+    "cmpxchg16b",
+    "f16c",
+    "fma",
+    "fxsr",
+    "gfni",
+    "kl",
+    "lzcnt",
+    "movbe",
+    "pclmulqdq",
+    "popcnt",
+    "rdrand",
+    "rdseed",
+    "sha",
+    "sha512",
+    "sm3",
+    "sm4",
+    "sse",
+    "sse2",
+    "sse3",
+    "sse4.1",
+    "sse4.2",
+    "sse4a",
+    "ssse3",
+    "tbm",
+    "vaes",
+    "vpclmulqdq",
+    "widekl",
+    "xsave",
+    "xsavec",
+    "xsaveopt",
+    "xsaves",
+];
+
+/// cfg options that are set by the compiler or by cargo without a value
+pub(crate) const BARE_CFGS: &[&str] = &[
+    "clippy",
+    "debug_assertions",
+    "doc",
+    "doctest",
+    "miri",
+    "overflow_checks",
+    "proc_macro",
+    "rustfmt",
+    "target_thread_local",
+    "test",
+    "ub_checks",
+    "unix",
+    "windows",
+];
+
+/// the table of well-known values for `key`, if there is one
+pub(crate) fn values(key: &str) -> Option<&'static [&'static str]> {
+    match key {
+        "target_arch" => Some(TARGET_ARCH),
+        "target_vendor" => Some(TARGET_VENDOR),
+        "target_os" => Some(TARGET_OS),
+        "target_env" => Some(TARGET_ENV),
+        "target_family" => Some(TARGET_FAMILY),
+        _ => None,
+    }
+}
+
+/// match `value` against a glob `pattern`, where `*` matches any sequence of characters and `?`
+/// matches exactly one character
+pub(crate) fn glob_match(pattern: &str, value: &str) -> bool {
+    let (pattern, value): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), value.chars().collect());
+    let (mut p, mut v) = (0, 0);
+    // position of the last `*` in the pattern, and the position in the value it was tried at
+    let mut backtrack = None;
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                // let the last `*` swallow one more character
+                Some((star, star_v)) => {
+                    backtrack = Some((star, star_v + 1));
+                    p = star + 1;
+                    v = star_v + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// trailing triple components that only describe the ABI, and don't affect `target_env`. they
+/// are dropped, since `target_abi` needs Rust 1.78, so `thumbv7em-none-eabi` and
+/// `thumbv7em-none-eabihf` lower to the same checks
+const TRIPLE_ABI_ONLY: &[&str] = &[
+    "abi64",
+    "abiv2",
+    "abiv2hf",
+    "eabi",
+    "eabihf",
+    "elf",
+    "softfloat",
+    "spe",
+];
+
+/// trailing triple components that select a different platform than the triple without them,
+/// which can't be told apart without `target_abi`
+const TRIPLE_PLATFORM_ABI: &[&str] = &["macabi", "sim"];
+
+/// map the architecture component of a triple to its `target_arch`
+fn triple_arch(arch: &str) -> Option<&'static str> {
+    if let Some(known) = TARGET_ARCH.iter().find(|known| **known == arch) {
+        return Some(known);
+    }
+    let mapped = match arch {
+        "i386" | "i586" | "i686" => "x86",
+        "x86_64h" => "x86_64",
+        "powerpc64le" => "powerpc64",
+        "sparcv9" => "sparc64",
+        "mipsel" => "mips",
+        "mips64el" => "mips64",
+        "mipsisa32r6" | "mipsisa32r6el" => "mips32r6",
+        "mipsisa64r6" | "mipsisa64r6el" => "mips64r6",
+        "amdgcn" => "amdgpu",
+        "bpfeb" | "bpfel" => "bpf",
+        "wasm32v1" => "wasm32",
+        _ if arch.starts_with("aarch64") || arch.starts_with("arm64") => "aarch64",
+        _ if arch.starts_with("arm") || arch.starts_with("thumb") => "arm",
+        _ if arch.starts_with("riscv64") => "riscv64",
+        _ if arch.starts_with("riscv32") => "riscv32",
+        _ => return None,
+    };
+    Some(mapped)
+}
+
+/// map the vendor component of a triple to its `target_vendor`
+fn triple_vendor(vendor: &str) -> Option<&'static str> {
+    let vendor = match vendor {
+        "esp" | "esp32" | "esp32s2" | "esp32s3" => "espressif",
+        "lynx" | "wali" => "unknown",
+        vendor => vendor,
+    };
+    TARGET_VENDOR
+        .iter()
+        .find(|known| **known == vendor)
+        .copied()
+}
+
+/// map the OS component of a triple to its `target_os`
+fn triple_os(os: &str) -> Option<&'static str> {
+    let os = match os {
+        "darwin" => "macos",
+        "wasip1" | "wasip2" | "wasip3" => "wasi",
+        "switch" | "3ds" => "horizon",
+        "v5" => "vexos",
+        os => os,
+    };
+    TARGET_OS.iter().find(|known| **known == os).copied()
+}
+
+/// map the environment component of a triple to its `target_env`. `Some("")` means that the
+/// component only describes the ABI
+fn triple_env(env: &str) -> Option<&'static str> {
+    if TRIPLE_ABI_ONLY.contains(&env) {
+        return Some("");
+    }
+    let mapped = match env {
+        "msvc" | "sgx" | "newlib" | "ohos" | "relibc" | "mlibc" => {
+            return TARGET_ENV.iter().find(|known| **known == env).copied()
+        }
+        // no C library at all
+        "none" | "freestanding" => "",
+        "qnx700" => "nto70",
+        "qnx710" => "nto71",
+        "qnx710_iosock" => "nto71_iosock",
+        "qnx800" => "nto80",
+        _ if env.starts_with("newlib") => "newlib",
+        _ if env.starts_with("gnu") => "gnu",
+        _ if env.starts_with("musl") => "musl",
+        _ if env.starts_with("uclibc") => "uclibc",
+        _ => return None,
+    };
+    Some(mapped)
+}
+
+/// Split a target triple into `(key, value)` cfg pairs.
+///
+/// A `*` component matches anything, and isn't turned into a cfg pair. Returns an error message
+/// if the triple has a component that isn't recognized.
+pub(crate) fn split_triple(triple: &str) -> Result<Vec<(&'static str, &'static str)>, String> {
+    let parts: Vec<&str> = triple.split('-').collect();
+    if parts.len() < 2 || parts.len() > 4 {
+        return Err(format!(
+            "`{}` is not a target triple (expected `arch-vendor-os-env`)",
+            triple
+        ));
+    }
+    // the vendor is optional; a three component triple is `arch-vendor-os` if the second
+    // component is a known vendor and `arch-os-env` otherwise
+    let has_vendor = parts.len() == 4
+        || (parts.len() == 3 && (parts[1] == "*" || triple_vendor(parts[1]).is_some()));
+    let (vendor, os, env) = match (has_vendor, &parts[1..]) {
+        (true, [vendor, os]) => (Some(*vendor), *os, None),
+        (true, [vendor, os, env]) => (Some(*vendor), *os, Some(*env)),
+        (false, [os]) => (None, *os, None),
+        (false, [os, env]) => (None, *os, Some(*env)),
+        _ => unreachable!(),
+    };
+
+    let mut pairs = Vec::new();
+    if parts[0] != "*" {
+        let arch = triple_arch(parts[0])
+            .ok_or_else(|| format!("unknown architecture `{}` in target triple", parts[0]))?;
+        pairs.push(("target_arch", arch));
+    }
+    if let Some(vendor) = vendor.filter(|vendor| *vendor != "*") {
+        let vendor = triple_vendor(vendor)
+            .ok_or_else(|| format!("unknown vendor `{}` in target triple", vendor))?;
+        pairs.push(("target_vendor", vendor));
+    }
+    // android triples are spelled `linux-android`, and `-threads` isn't an environment
+    let (os, env) = match (os, env) {
+        ("linux", Some("android")) | ("linux", Some("androideabi")) => ("android", None),
+        ("wasip1", Some("threads")) => ("wasip1", None),
+        other => other,
+    };
+    if os != "*" {
+        let os = triple_os(os)
+            .ok_or_else(|| format!("unknown operating system `{}` in target triple", os))?;
+        pairs.push(("target_os", os));
+    }
+    if let Some(env) = env.filter(|env| TRIPLE_PLATFORM_ABI.contains(env)) {
+        return Err(format!(
+            "the `{}` in `{}` can't be checked with cfg, so the triple would also match the \
+             targets without it; use `{}-*` to match all of them",
+            env,
+            triple,
+            parts[..parts.len() - 1].join("-")
+        ));
+    }
+    if let Some(env) = env.filter(|env| *env != "*") {
+        let env = triple_env(env)
+            .ok_or_else(|| format!("unknown environment `{}` in target triple", env))?;
+        if !env.is_empty() {
+            pairs.push(("target_env", env));
+        }
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_triples() {
+        // the triple, and its `target_arch`, `target_os` and `target_env` if the triple spells it
+        // out. ABI components like `eabihf` aren't checked, and neither is an environment the
+        // triple implies, like `p1` for `wasm32-wasip1`, so those triples have no `target_env`
+        const TRIPLES: &[(&str, &str, &str, Option<&str>)] = &[
+            ("aarch64-apple-darwin", "aarch64", "macos", None),
+            ("aarch64-linux-android", "aarch64", "android", None),
+            (
+                "aarch64-pc-windows-msvc",
+                "aarch64",
+                "windows",
+                Some("msvc"),
+            ),
+            ("aarch64-unknown-linux-gnu", "aarch64", "linux", Some("gnu")),
+            (
+                "aarch64-unknown-nto-qnx710_iosock",
+                "aarch64",
+                "nto",
+                Some("nto71_iosock"),
+            ),
+            (
+                "aarch64-nintendo-switch-freestanding",
+                "aarch64",
+                "horizon",
+                None,
+            ),
+            ("amdgcn-amd-amdhsa", "amdgpu", "amdhsa", None),
+            ("arm64_32-apple-watchos", "aarch64", "watchos", None),
+            ("arm64e-apple-darwin", "aarch64", "macos", None),
+            ("arm64e-apple-ios", "aarch64", "ios", None),
+            ("arm64e-apple-tvos", "aarch64", "tvos", None),
+            (
+                "arm64ec-pc-windows-msvc",
+                "arm64ec",
+                "windows",
+                Some("msvc"),
+            ),
+            ("arm-linux-androideabi", "arm", "android", None),
+            ("armv6k-nintendo-3ds", "arm", "horizon", None),
+            (
+                "armv7-sony-vita-newlibeabihf",
+                "arm",
+                "vita",
+                Some("newlib"),
+            ),
+            ("armv7-unknown-linux-gnueabihf", "arm", "linux", Some("gnu")),
+            ("armv7a-vex-v5", "arm", "vexos", None),
+            ("bpfel-unknown-none", "bpf", "none", None),
+            ("i686-pc-nto-qnx700", "x86", "nto", Some("nto70")),
+            ("i686-pc-windows-gnu", "x86", "windows", Some("gnu")),
+            ("i686-unknown-linux-musl", "x86", "linux", Some("musl")),
+            (
+                "loongarch32-unknown-none-softfloat",
+                "loongarch32",
+                "none",
+                None,
+            ),
+            (
+                "loongarch64-unknown-linux-ohos",
+                "loongarch64",
+                "linux",
+                Some("ohos"),
+            ),
+            ("mips64-openwrt-linux-musl", "mips64", "linux", Some("musl")),
+            ("mipsel-mti-none-elf", "mips", "none", None),
+            (
+                "mipsisa64r6el-unknown-linux-gnuabi64",
+                "mips64r6",
+                "linux",
+                Some("gnu"),
+            ),
+            (
+                "powerpc64le-unknown-linux-gnu",
+                "powerpc64",
+                "linux",
+                Some("gnu"),
+            ),
+            ("riscv32imac-esp-espidf", "riscv32", "espidf", None),
+            (
+                "riscv64gc-unknown-managarm-mlibc",
+                "riscv64",
+                "managarm",
+                Some("mlibc"),
+            ),
+            ("sparcv9-sun-solaris", "sparc64", "solaris", None),
+            ("thumbv7em-none-eabihf", "arm", "none", None),
+            ("wasm32-unknown-emscripten", "wasm32", "emscripten", None),
+            ("wasm32-wali-linux-musl", "wasm32", "linux", Some("musl")),
+            ("wasm32-wasip1", "wasm32", "wasi", None),
+            ("wasm32-wasip1-threads", "wasm32", "wasi", None),
+            ("wasm32-wasip2", "wasm32", "wasi", None),
+            ("wasm32v1-none", "wasm32", "none", None),
+            ("x86_64-apple-darwin", "x86_64", "macos", None),
+            (
+                "x86_64-fortanix-unknown-sgx",
+                "x86_64",
+                "unknown",
+                Some("sgx"),
+            ),
+            ("x86_64-lynx-lynxos178", "x86_64", "lynxos178", None),
+            ("x86_64-pc-cygwin", "x86_64", "cygwin", None),
+            ("x86_64-pc-nto-qnx800", "x86_64", "nto", Some("nto80")),
+            ("x86_64-unknown-linux-gnu", "x86_64", "linux", Some("gnu")),
+            ("x86_64-unknown-linux-none", "x86_64", "linux", None),
+            ("x86_64-unknown-redox", "x86_64", "redox", None),
+            ("x86_64-unknown-uefi", "x86_64", "uefi", None),
+            ("x86_64h-apple-darwin", "x86_64", "macos", None),
+            ("xtensa-esp32-espidf", "xtensa", "espidf", None),
+            ("xtensa-esp32s3-none-elf", "xtensa", "none", None),
+        ];
+        for (triple, arch, os, env) in TRIPLES {
+            let pairs = split_triple(triple).unwrap_or_else(|e| panic!("{}: {}", triple, e));
+            let get = |key| {
+                pairs
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, value)| *value)
+            };
+            assert_eq!(get("target_arch"), Some(*arch), "{}", triple);
+            assert_eq!(get("target_os"), Some(*os), "{}", triple);
+            assert_eq!(get("target_env"), *env, "{}", triple);
+        }
+    }
+
+    #[test]
+    fn platform_abi_triples() {
+        assert_eq!(
+            split_triple("aarch64-apple-ios-sim").unwrap_err(),
+            "the `sim` in `aarch64-apple-ios-sim` can't be checked with cfg, so the triple would \
+             also match the targets without it; use `aarch64-apple-ios-*` to match all of them"
+        );
+        assert!(split_triple("x86_64-apple-ios-macabi").is_err());
+        assert!(split_triple("aarch64-apple-ios-*").is_ok());
+    }
+}
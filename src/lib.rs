@@ -3,12 +3,89 @@ use {
     syn::{parse::Result as ParseResult, parse_macro_input},
 };
 
+mod backend;
+mod dispatch;
 mod grammar;
+mod kconfig;
+mod known;
+mod manifest;
+mod named;
 mod parse;
+mod shorthand;
+mod template;
+mod when;
+
+/// forget what the previous expansion read from the manifest and the `.config` file
+fn begin_expansion() {
+    manifest::reset();
+    kconfig::reset();
+}
 
 #[proc_macro]
 pub fn pragma(input: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(input as parse::PragmaInput);
-    let output = parse::process_pragma_input(input);
+    begin_expansion();
+    let input = parse_macro_input!(input as named::PragmaInvocation);
+    let output = input.expand();
+    output.into()
+}
+
+/// Define a named condition that can be used in `pragma!` blocks as `(if @name)`
+///
+/// ```ignore
+/// pragma::define_condition!(pub server = feature = "server" and unix);
+/// ```
+///
+/// The condition is stored in a `macro_rules!` macro called `name`, so it is imported and exported
+/// like any other macro: `pub` conditions are `#[macro_export]`ed, and can be used from other
+/// crates after a `use other_crate::name;`.
+///
+/// Aliases, ladders, `dep` checks and `CONFIG_` symbols are resolved against the crate that defines
+/// the condition, not the crates that use it.
+#[proc_macro]
+pub fn define_condition(input: TokenStream) -> TokenStream {
+    begin_expansion();
+    let input = parse_macro_input!(input as named::DefineCondition);
+    let output = named::expand_define_condition(input);
     output.into()
 }
+
+/// Load one of several backend modules, and re-export it under a single path
+///
+/// ```ignore
+/// pragma::select_backend! {
+///     pub mod imp {
+///         unix => "imp/unix.rs",
+///         windows => "imp/windows.rs",
+///         _ => "imp/fallback.rs",
+///     }
+/// }
+/// ```
+///
+/// The arms are tried in order, like an `else if` chain. Without a `_` arm, building for a target
+/// that no arm matches is a compile error.
+#[proc_macro]
+pub fn select_backend(input: TokenStream) -> TokenStream {
+    begin_expansion();
+    let input = parse_macro_input!(input as backend::SelectBackend);
+    let output = backend::expand_select_backend(input);
+    output.into()
+}
+
+/// Put a single item under a condition, without wrapping it in `pragma!`
+///
+/// ```ignore
+/// #[pragma::when(target_os = "linux" and not(test))]
+/// pub fn f() {}
+/// ```
+///
+/// This is the same as `pragma! { pub (if target_os = "linux" and not(test)) fn f() {} }`, so a
+/// `pub` item gets a private fallback when the condition doesn't hold.
+#[proc_macro_attribute]
+pub fn when(attr: TokenStream, item: TokenStream) -> TokenStream {
+    begin_expansion();
+    let item = parse_macro_input!(item as syn::Item);
+    match when::expand_when(attr.into(), item) {
+        Ok(output) => output.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
@@ -0,0 +1,709 @@
+//! Reading the manifest (`Cargo.toml`) of the crate that is being compiled
+//!
+//! This is a small TOML reader that supports everything a manifest normally uses: tables, arrays
+//! of tables, dotted and quoted keys, inline tables, arrays and all string forms. Values that we
+//! never need to look at (numbers and dates) are skipped.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    env, fs, io,
+    path::PathBuf,
+    rc::Rc,
+};
+
+pub(crate) type Table = BTreeMap<String, Value>;
+
+pub(crate) enum Value {
+    String(String),
+    Bool(bool),
+    /// numbers and dates
+    Other,
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+    pub(crate) fn as_table(&self) -> Option<&Table> {
+        match self {
+            Value::Table(t) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+/// the path to the manifest of the crate being compiled
+pub(crate) fn manifest_path() -> Result<PathBuf, String> {
+    let dir = env::var_os("CARGO_MANIFEST_DIR")
+        .ok_or("`CARGO_MANIFEST_DIR` is not set; is this crate being built by cargo?")?;
+    Ok(PathBuf::from(dir).join("Cargo.toml"))
+}
+
+thread_local! {
+    /// the manifest of the crate being compiled, as read during the current expansion. `None`
+    /// inside the result means that there is no manifest
+    static CACHE: RefCell<Option<Result<Option<Rc<Table>>, String>>> = const { RefCell::new(None) };
+    /// whether a condition of the current expansion looked at the manifest
+    static CONSULTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// forget the cached manifest. This is called at the start of every expansion, so that the
+/// manifest is read at most once per expansion, and edits to it are picked up by the next one
+pub(crate) fn reset() {
+    CACHE.with(|cache| *cache.borrow_mut() = None);
+    CONSULTED.with(|consulted| consulted.set(false));
+}
+
+/// check if the manifest was looked at since the last call, so that the expansion can tell cargo
+/// to rebuild the crate when the manifest changes
+pub(crate) fn take_consulted() -> bool {
+    CONSULTED.with(|consulted| consulted.replace(false))
+}
+
+/// read and parse the manifest of the crate being compiled, failing if there is none
+pub(crate) fn load() -> Result<Rc<Table>, String> {
+    match load_if_present()? {
+        Some(table) => Ok(table),
+        None => Err(match manifest_path() {
+            Ok(path) => format!("`{}` doesn't exist", path.display()),
+            Err(e) => e,
+        }),
+    }
+}
+
+/// read and parse the manifest of the crate being compiled, if the crate is being built by cargo
+/// and has one
+pub(crate) fn load_if_present() -> Result<Option<Rc<Table>>, String> {
+    let manifest = CACHE.with(|cache| {
+        cache
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                let path = match manifest_path() {
+                    Ok(path) => path,
+                    Err(_) => return Ok(None),
+                };
+                let source = match fs::read_to_string(&path) {
+                    Ok(source) => source,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                    Err(e) => return Err(format!("failed to read `{}`: {}", path.display(), e)),
+                };
+                parse(&source)
+                    .map(|table| Some(Rc::new(table)))
+                    .map_err(|e| format!("failed to parse `{}`: {}", path.display(), e))
+            })
+            .clone()
+    });
+    if let Ok(Some(_)) = manifest {
+        CONSULTED.with(|consulted| consulted.set(true));
+    }
+    manifest
+}
+
+/// use `source` as the manifest for the rest of the current expansion, or pretend that there is
+/// no manifest
+#[cfg(test)]
+pub(crate) fn set(source: Option<&str>) {
+    let table = source.map(|source| Rc::new(parse(source).expect("invalid manifest")));
+    CACHE.with(|cache| *cache.borrow_mut() = Some(Ok(table)));
+}
+
+/// look up a value by its dotted path, for example `["package", "metadata", "pragma"]`
+pub(crate) fn get<'a>(table: &'a Table, path: &[&str]) -> Option<&'a Value> {
+    let (last, parents) = path.split_last()?;
+    let mut table = table;
+    for key in parents {
+        table = table.get(*key)?.as_table()?;
+    }
+    table.get(*last)
+}
+
+/// every dependency declared in the manifest, including dev, build and target specific
+/// dependencies, along with whether it is optional
+pub(crate) fn dependencies(manifest: &Table) -> Vec<(&str, bool)> {
+    const KINDS: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+    let targets = manifest
+        .get("target")
+        .and_then(Value::as_table)
+        .into_iter()
+        .flat_map(|targets| targets.values().filter_map(Value::as_table));
+    std::iter::once(manifest)
+        .chain(targets)
+        .flat_map(|table| KINDS.iter().filter_map(move |kind| table.get(*kind)))
+        .filter_map(Value::as_table)
+        .flat_map(|deps| deps.iter())
+        .map(|(name, spec)| {
+            let optional = spec
+                .as_table()
+                .and_then(|spec| spec.get("optional"))
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            (name.as_str(), optional)
+        })
+        .collect()
+}
+
+/// parse a TOML document
+pub(crate) fn parse(source: &str) -> Result<Table, String> {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        pos: 0,
+    };
+    parser.document().map_err(|e| {
+        let line = parser.chars[..parser.pos.min(parser.chars.len())]
+            .iter()
+            .filter(|c| **c == '\n')
+            .count()
+            + 1;
+        format!("{} (line {})", e, line)
+    })
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected `{}`", c))
+        }
+    }
+    /// skip spaces and tabs
+    fn skip_ws(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.pos += 1;
+        }
+    }
+    /// skip whitespace, newlines and comments
+    fn skip_ws_and_newlines(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ') | Some('\t') | Some('\r') | Some('\n') => self.pos += 1,
+                Some('#') => self.skip_comment(),
+                _ => return,
+            }
+        }
+    }
+    fn skip_comment(&mut self) {
+        while !matches!(self.peek(), None | Some('\n')) {
+            self.pos += 1;
+        }
+    }
+    /// skip the rest of a line after a key/value pair or a header
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_ws();
+        if self.peek() == Some('#') {
+            self.skip_comment();
+        }
+        match self.peek() {
+            None | Some('\n') => Ok(()),
+            Some('\r') if self.chars.get(self.pos + 1) == Some(&'\n') => Ok(()),
+            Some(c) => Err(format!("unexpected `{}` at the end of a line", c)),
+        }
+    }
+
+    fn document(&mut self) -> Result<Table, String> {
+        let mut root = Table::new();
+        // the path of the table that key/value pairs are currently added to
+        let mut current: Vec<String> = Vec::new();
+        loop {
+            self.skip_ws_and_newlines();
+            match self.peek() {
+                None => return Ok(root),
+                Some('[') => {
+                    self.pos += 1;
+                    let array = self.peek() == Some('[');
+                    if array {
+                        self.pos += 1;
+                    }
+                    self.skip_ws();
+                    current = self.key()?;
+                    self.skip_ws();
+                    self.expect(']')?;
+                    if array {
+                        self.expect(']')?;
+                    }
+                    self.end_of_line()?;
+                    if array {
+                        let (last, parents) = current.split_last().unwrap();
+                        let parent = table_at(&mut root, parents)?;
+                        match parent
+                            .entry(last.clone())
+                            .or_insert_with(|| Value::Array(Vec::new()))
+                        {
+                            Value::Array(tables) => tables.push(Value::Table(Table::new())),
+                            _ => return Err(format!("`{}` is not an array of tables", last)),
+                        }
+                    } else {
+                        table_at(&mut root, &current)?;
+                    }
+                }
+                Some(_) => {
+                    let table = table_at(&mut root, &current)?;
+                    self.key_value(table)?;
+                    self.end_of_line()?;
+                }
+            }
+        }
+    }
+
+    /// parse a `key = value` pair into `table`
+    fn key_value(&mut self, table: &mut Table) -> Result<(), String> {
+        let key = self.key()?;
+        self.skip_ws();
+        self.expect('=')?;
+        self.skip_ws();
+        let value = self.value()?;
+        let (last, parents) = key.split_last().unwrap();
+        table_at(table, parents)?.insert(last.clone(), value);
+        Ok(())
+    }
+
+    /// parse a (possibly dotted) key
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_ws();
+            let part = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let start = self.pos;
+                    while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                    {
+                        self.pos += 1;
+                    }
+                    if start == self.pos {
+                        return Err("expected a key".to_owned());
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            parts.push(part);
+            self.skip_ws();
+            if self.peek() == Some('.') {
+                self.pos += 1;
+            } else {
+                return Ok(parts);
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') => Ok(Value::String(self.basic_string()?)),
+            Some('\'') => Ok(Value::String(self.literal_string()?)),
+            Some('[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                loop {
+                    self.skip_ws_and_newlines();
+                    if self.peek() == Some(']') {
+                        self.pos += 1;
+                        return Ok(Value::Array(values));
+                    }
+                    values.push(self.value()?);
+                    self.skip_ws_and_newlines();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some(']') => {}
+                        _ => return Err("expected `,` or `]` in array".to_owned()),
+                    }
+                }
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut table = Table::new();
+                self.skip_ws();
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                    return Ok(Value::Table(table));
+                }
+                loop {
+                    self.key_value(&mut table)?;
+                    self.skip_ws();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some('}') => {
+                            self.pos += 1;
+                            return Ok(Value::Table(table));
+                        }
+                        _ => return Err("expected `,` or `}` in inline table".to_owned()),
+                    }
+                }
+            }
+            Some(_) => {
+                let start = self.pos;
+                while !matches!(
+                    self.peek(),
+                    None | Some(',') | Some(']') | Some('}') | Some('#') | Some('\n') | Some('\r')
+                ) {
+                    self.pos += 1;
+                }
+                let raw: String = self.chars[start..self.pos].iter().collect();
+                match raw.trim_end() {
+                    "" => Err("expected a value".to_owned()),
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => Ok(Value::Other),
+                }
+            }
+            None => Err("expected a value".to_owned()),
+        }
+    }
+
+    /// parse a `"basic"` or `"""multi-line basic"""` string
+    fn basic_string(&mut self) -> Result<String, String> {
+        let multiline = self.starts_with("\"\"\"");
+        if multiline {
+            self.pos += 3;
+            // a newline right after the opening delimiter is trimmed
+            if self.starts_with("\r\n") {
+                self.pos += 2;
+            } else if self.peek() == Some('\n') {
+                self.pos += 1;
+            }
+        } else {
+            self.pos += 1;
+        }
+        let mut out = String::new();
+        loop {
+            match self.peek() {
+                None => return Err("unterminated string".to_owned()),
+                Some('"') if !multiline => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some('"') if self.starts_with("\"\"\"") && !self.starts_with("\"\"\"\"") => {
+                    self.pos += 3;
+                    return Ok(out);
+                }
+                Some('\n') if !multiline => return Err("unterminated string".to_owned()),
+                Some('\\') => {
+                    self.pos += 1;
+                    let escaped = self.peek().ok_or("unterminated string")?;
+                    self.pos += 1;
+                    match escaped {
+                        'b' => out.push('\u{8}'),
+                        't' => out.push('\t'),
+                        'n' => out.push('\n'),
+                        'f' => out.push('\u{c}'),
+                        'r' => out.push('\r'),
+                        '"' => out.push('"'),
+                        '\\' => out.push('\\'),
+                        'u' | 'U' => {
+                            let len = if escaped == 'u' { 4 } else { 8 };
+                            let hex: String = self
+                                .chars
+                                .get(self.pos..self.pos + len)
+                                .ok_or("unterminated escape")?
+                                .iter()
+                                .collect();
+                            self.pos += len;
+                            let c = u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| format!("invalid escape `\\{}{}`", escaped, hex))?;
+                            out.push(c);
+                        }
+                        // line ending backslash in multi-line strings
+                        c if multiline && c.is_whitespace() => {
+                            while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+                                self.pos += 1;
+                            }
+                        }
+                        c => return Err(format!("invalid escape `\\{}`", c)),
+                    }
+                }
+                Some(c) => {
+                    out.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// parse a `'literal'` or `'''multi-line literal'''` string
+    fn literal_string(&mut self) -> Result<String, String> {
+        let multiline = self.starts_with("'''");
+        if multiline {
+            self.pos += 3;
+            if self.starts_with("\r\n") {
+                self.pos += 2;
+            } else if self.peek() == Some('\n') {
+                self.pos += 1;
+            }
+        } else {
+            self.pos += 1;
+        }
+        let mut out = String::new();
+        loop {
+            match self.peek() {
+                None => return Err("unterminated string".to_owned()),
+                Some('\'') if !multiline => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some('\'') if self.starts_with("'''") && !self.starts_with("''''") => {
+                    self.pos += 3;
+                    return Ok(out);
+                }
+                Some('\n') if !multiline => return Err("unterminated string".to_owned()),
+                Some(c) => {
+                    out.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+}
+
+/// get the table at `path`, creating it if it doesn't exist. for arrays of tables, the last table
+/// in the array is used
+fn table_at<'a>(root: &'a mut Table, path: &[String]) -> Result<&'a mut Table, String> {
+    let mut table = root;
+    for key in path {
+        let value = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        table = match value {
+            Value::Table(t) => t,
+            Value::Array(tables) => match tables.last_mut() {
+                Some(Value::Table(t)) => t,
+                _ => return Err(format!("`{}` is not a table", key)),
+            },
+            _ => return Err(format!("`{}` is not a table", key)),
+        };
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn str_at<'a>(table: &'a Table, path: &[&str]) -> &'a str {
+        get(table, path).and_then(Value::as_str).unwrap()
+    }
+
+    #[test]
+    fn arrays_of_tables() {
+        let table = parse(
+            r#"
+[[bin]]
+name = "a"
+
+[[bin]]
+name = "b"
+[bin.metadata]
+extra = true
+"#,
+        )
+        .unwrap();
+        let bins = match table.get("bin") {
+            Some(Value::Array(bins)) => bins,
+            _ => panic!("`bin` is not an array"),
+        };
+        assert_eq!(bins.len(), 2);
+        assert_eq!(str_at(bins[0].as_table().unwrap(), &["name"]), "a");
+        assert_eq!(str_at(bins[1].as_table().unwrap(), &["name"]), "b");
+        // a table header below an array of tables belongs to its last table
+        assert_eq!(
+            get(bins[1].as_table().unwrap(), &["metadata", "extra"]).and_then(Value::as_bool),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn dotted_and_quoted_keys() {
+        let table = parse(
+            r#"
+package.metadata.pragma.kconfig = ".config"
+"quoted key" = "a"
+'literal key' = "b"
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+
+[target."x86_64-pc-windows-msvc" . dependencies]
+winapi = { version = "0.3", optional = true }
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            str_at(&table, &["package", "metadata", "pragma", "kconfig"]),
+            ".config"
+        );
+        assert_eq!(str_at(&table, &["quoted key"]), "a");
+        assert_eq!(str_at(&table, &["literal key"]), "b");
+        assert_eq!(
+            str_at(&table, &["target", "cfg(unix)", "dependencies", "libc"]),
+            "0.2"
+        );
+        assert_eq!(
+            dependencies(&table),
+            vec![("libc", false), ("winapi", true)]
+        );
+    }
+
+    #[test]
+    fn inline_tables_and_arrays() {
+        let table = parse(
+            r#"
+a = { b = { c = "d" }, e = [1, 2.5, "f"], g = false, h = {} }
+i = [
+    "j",
+    { k = "l" },
+    [],
+]
+"#,
+        )
+        .unwrap();
+        assert_eq!(str_at(&table, &["a", "b", "c"]), "d");
+        assert!(matches!(get(&table, &["a", "e"]), Some(Value::Array(e)) if e.len() == 3));
+        assert_eq!(
+            get(&table, &["a", "g"]).and_then(Value::as_bool),
+            Some(false)
+        );
+        assert!(get(&table, &["a", "h"]).and_then(Value::as_table).is_some());
+        match get(&table, &["i"]) {
+            Some(Value::Array(i)) => {
+                assert_eq!(i[0].as_str(), Some("j"));
+                assert_eq!(str_at(i[1].as_table().unwrap(), &["k"]), "l");
+                assert!(matches!(&i[2], Value::Array(empty) if empty.is_empty()));
+            }
+            _ => panic!("`i` is not an array"),
+        }
+    }
+
+    #[test]
+    fn strings() {
+        let table = parse(
+            r#"
+basic = "tab\tquote\"backslash\\\u00e9\U0001F600"
+literal = 'C:\no\escapes'
+multi = """
+one \
+    two
+three"""
+multi_literal = '''
+raw \n "quotes" '''
+quote_before_end = """a "quoted" "word""""
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            str_at(&table, &["basic"]),
+            "tab\tquote\"backslash\\\u{e9}\u{1f600}"
+        );
+        assert_eq!(str_at(&table, &["literal"]), r"C:\no\escapes");
+        // the newline after the opening quotes is trimmed, and a backslash at the end of a line
+        // trims the whitespace up to the next character
+        assert_eq!(str_at(&table, &["multi"]), "one two\nthree");
+        assert_eq!(str_at(&table, &["multi_literal"]), r#"raw \n "quotes" "#);
+        assert_eq!(
+            str_at(&table, &["quote_before_end"]),
+            r#"a "quoted" "word""#
+        );
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let table = parse(
+            "[package]\r\nname = \"crlf\" # comment\r\n\r\n[features]\r\n\
+             default = [\r\n    \"std\",\r\n]\r\ndoc = \"\"\"\r\nline \\\r\n  joined\"\"\"\r\n",
+        )
+        .unwrap();
+        assert_eq!(str_at(&table, &["package", "name"]), "crlf");
+        assert!(matches!(
+            get(&table, &["features", "default"]),
+            Some(Value::Array(default)) if default[0].as_str() == Some("std")
+        ));
+        assert_eq!(str_at(&table, &["features", "doc"]), "line joined");
+    }
+
+    #[test]
+    fn comments_after_values() {
+        let table = parse(
+            r#"
+[package] # the package
+name = "x" # the name
+version = 1 # not a string
+edition = 'y'# no space
+list = [ # opening
+    "a", # first
+    "b" # second
+] # closing
+"#,
+        )
+        .unwrap();
+        assert_eq!(str_at(&table, &["package", "name"]), "x");
+        assert!(matches!(
+            get(&table, &["package", "version"]),
+            Some(Value::Other)
+        ));
+        assert_eq!(str_at(&table, &["package", "edition"]), "y");
+        assert!(matches!(
+            get(&table, &["package", "list"]),
+            Some(Value::Array(list)) if list.len() == 2
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source| {
+            parse(source)
+                .err()
+                .unwrap_or_else(|| panic!("{:?} parsed", source))
+        };
+        assert_eq!(
+            error("a = \"unterminated\nb = 1"),
+            "unterminated string (line 1)"
+        );
+        assert_eq!(
+            error("a = 1\n\nb = \"x\" c\n"),
+            "unexpected `c` at the end of a line (line 3)"
+        );
+        assert_eq!(
+            error("a = 1\nb = [\"x\" \"y\"]"),
+            "expected `,` or `]` in array (line 2)"
+        );
+        assert_eq!(
+            error("a = { b = \"x\" c = 2 }"),
+            "expected `,` or `}` in inline table (line 1)"
+        );
+        assert_eq!(error("a = \"\\q\""), "invalid escape `\\q` (line 1)");
+        assert_eq!(error("\n= 1"), "expected a key (line 2)");
+        assert_eq!(error("a ="), "expected a value (line 1)");
+        assert_eq!(error("[a\nb = 1"), "expected `]` (line 1)");
+        assert_eq!(
+            error("[a]\nb = 1\n[[a]]"),
+            "`a` is not an array of tables (line 3)"
+        );
+        assert_eq!(error("a = 1\na.b = 2"), "`a` is not a table (line 2)");
+    }
+}
@@ -0,0 +1,305 @@
+//! Named conditions, defined with `define_condition!` and referenced with `@name`
+//!
+//! A proc macro can't look up the definition of a condition in another crate, so every named
+//! condition is stored in a `macro_rules!` macro of the same name. When a `pragma!` block refers
+//! to a condition it hasn't seen yet, it expands to a call to that macro, which calls `pragma!`
+//! again with the definition prepended to the input:
+//!
+//! ```text
+//! pragma! { (if @server) fn f() {} }
+//! => server! { @pragma_resolve {} (if @server) fn f() {} }
+//! => ::pragma::pragma! { @resolved { server = (cfg(all(feature = "server", r#unix))); } (if @server) fn f() {} }
+//! ```
+//!
+//! The definition is lowered to a `cfg(..)` predicate where it's defined, so that aliases,
+//! ladders, `dep` checks and `CONFIG_` symbols refer to the defining crate rather than the one
+//! that uses the condition.
+
+use {
+    crate::{
+        grammar::{self, ConditionExpr},
+        parse::{self, PragmaInput},
+        template::CondTemplate,
+        ParseResult,
+    },
+    proc_macro2::{Group, TokenStream, TokenTree},
+    quote::quote,
+    std::rc::Rc,
+    syn::{
+        parse::{Parse, ParseStream, Parser},
+        Ident, Token, Visibility,
+    },
+};
+
+/// `define_condition!(pub name = condition)`
+pub(crate) struct DefineCondition {
+    visibility: Visibility,
+    name: Ident,
+    condition: ConditionExpr,
+}
+
+impl Parse for DefineCondition {
+    fn parse(input: ParseStream) -> ParseResult<Self> {
+        let visibility: Visibility = input.parse()?;
+        let name: Ident = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let condition: TokenStream = input.parse()?;
+        let condition = Parser::parse2(
+            |input: ParseStream| grammar::parse_condition(&input),
+            condition,
+        )?;
+        Ok(DefineCondition {
+            visibility,
+            name,
+            condition,
+        })
+    }
+}
+
+pub(crate) fn expand_define_condition(define: DefineCondition) -> TokenStream {
+    let DefineCondition {
+        visibility,
+        name,
+        condition,
+    } = define;
+    let dependencies = grammar::condition_dependencies(&condition);
+    let condition = lower(&condition);
+    let export = match &visibility {
+        Visibility::Public(_) => quote! { #[macro_export] },
+        _ => quote! {},
+    };
+    let reexport = match &visibility {
+        Visibility::Restricted(_) => quote! {
+            #[allow(unused_imports)]
+            #visibility use #name;
+        },
+        _ => quote! {},
+    };
+    quote! {
+        #export
+        macro_rules! #name {
+            (@pragma_resolve { $($resolved:tt)* } $($body:tt)*) => {
+                ::pragma::pragma! {
+                    @resolved { $($resolved)* #name = (#condition); }
+                    $($body)*
+                }
+            };
+        }
+        #reexport
+        #dependencies
+    }
+}
+
+/// lower `cond` to a condition that means the same in every crate. everything that is resolved
+/// against the defining crate becomes a `cfg(..)` predicate, and only the references to other
+/// named conditions and templates are kept
+fn lower(cond: &ConditionExpr) -> TokenStream {
+    let mut unresolved = false;
+    cond.visit(&mut |cond| {
+        unresolved |= matches!(cond, ConditionExpr::Named(_) | ConditionExpr::Call(..))
+    });
+    if !unresolved {
+        let cfg = raw_keys(grammar::condition_to_cfg(cond));
+        return quote! { cfg(#cfg) };
+    }
+    match cond {
+        ConditionExpr::All(exprs) => {
+            let exprs = exprs.iter().map(lower);
+            quote! { all(#(#exprs),*) }
+        }
+        ConditionExpr::Any(exprs) => {
+            let exprs = exprs.iter().map(lower);
+            quote! { any(#(#exprs),*) }
+        }
+        ConditionExpr::ExactlyOne(exprs) => {
+            let exprs = exprs.iter().map(lower);
+            quote! { exactly_one_of(#(#exprs),*) }
+        }
+        ConditionExpr::AtMostOne(exprs) => {
+            let exprs = exprs.iter().map(lower);
+            quote! { at_most_one_of(#(#exprs),*) }
+        }
+        ConditionExpr::Not(expr) => {
+            let expr = lower(expr);
+            quote! { not(#expr) }
+        }
+        ConditionExpr::Xor(lhs, rhs) => {
+            let (lhs, rhs) = (lower(lhs), lower(rhs));
+            quote! { (#lhs) xor (#rhs) }
+        }
+        ConditionExpr::Implies(lhs, rhs) => {
+            let (lhs, rhs) = (lower(lhs), lower(rhs));
+            quote! { (#lhs) implies (#rhs) }
+        }
+        ConditionExpr::Named(name) => quote! { @#name },
+        ConditionExpr::Call(name, args) => quote! { #name(#(#args),*) },
+        _ => unreachable!("only conditions with children can contain named conditions"),
+    }
+}
+
+/// make the bare keys of a cfg predicate raw identifiers, so that the crate that uses the
+/// condition doesn't take them for its own aliases or shorthands
+fn raw_keys(tokens: TokenStream) -> TokenStream {
+    let mut tokens = tokens.into_iter().peekable();
+    let mut raw = TokenStream::new();
+    while let Some(token) = tokens.next() {
+        let token = match token {
+            TokenTree::Ident(ident) => {
+                // `all(..)`, `any(..)` and `not(..)` are followed by a group, and the keys of
+                // `key = "value"` by a `=`
+                let bare = match tokens.peek() {
+                    Some(TokenTree::Group(_)) => false,
+                    Some(TokenTree::Punct(punct)) => punct.as_char() != '=',
+                    _ => true,
+                };
+                if bare {
+                    TokenTree::Ident(Ident::new_raw(&ident.to_string(), ident.span()))
+                } else {
+                    TokenTree::Ident(ident)
+                }
+            }
+            TokenTree::Group(group) => {
+                let mut new = Group::new(group.delimiter(), raw_keys(group.stream()));
+                new.set_span(group.span());
+                TokenTree::Group(new)
+            }
+            token => token,
+        };
+        raw.extend(std::iter::once(token));
+    }
+    raw
+}
+
+/// A `pragma!` invocation, along with the named conditions that have been resolved so far
+pub(crate) struct PragmaInvocation {
+    resolved: Vec<(Ident, TokenStream)>,
+    body: TokenStream,
+    input: PragmaInput,
+}
+
+impl Parse for PragmaInvocation {
+    fn parse(input: ParseStream) -> ParseResult<Self> {
+        // parse `@resolved { name = (condition); .. }`
+        let mut resolved = Vec::new();
+        if input.peek(Token![@]) && input.peek2(Ident) {
+            let fork = input.fork();
+            fork.parse::<Token![@]>()?;
+            if fork.parse::<Ident>()? == "resolved" {
+                input.parse::<Token![@]>()?;
+                input.parse::<Ident>()?;
+                let content;
+                let _brace = syn::braced!(content in input);
+                while !content.is_empty() {
+                    let name: Ident = content.parse()?;
+                    content.parse::<Token![=]>()?;
+                    let condition;
+                    let _paren = syn::parenthesized!(condition in content);
+                    resolved.push((name, condition.parse()?));
+                    content.parse::<Token![;]>()?;
+                }
+            }
+        }
+        let body: TokenStream = input.fork().parse()?;
+        let input: PragmaInput = input.parse()?;
+        Ok(PragmaInvocation {
+            resolved,
+            body,
+            input,
+        })
+    }
+}
+
+impl PragmaInvocation {
+    pub(crate) fn expand(self) -> TokenStream {
+        let PragmaInvocation {
+            resolved,
+            body,
+            mut input,
+        } = self;
+        match substitute_named(&mut input, &resolved) {
+            Ok(None) => parse::process_pragma_input(input),
+            Ok(Some(unresolved)) => {
+                // ask the macro of the condition for its definition
+                let resolved = resolved
+                    .iter()
+                    .map(|(name, condition)| quote! { #name = (#condition); });
+                quote! {
+                    #unresolved! {
+                        @pragma_resolve { #(#resolved)* }
+                        #body
+                    }
+                }
+            }
+            Err(e) => e.to_compile_error(),
+        }
+    }
+}
+
+/// substitute every `@name` and every call to a condition template in the conditions of `input`
+/// with its definition. returns the first name that hasn't been resolved yet, if any
+fn substitute_named(
+    input: &mut PragmaInput,
+    resolved: &[(Ident, TokenStream)],
+) -> ParseResult<Option<Ident>> {
+    let mut unresolved = None;
+    input.for_each_condition_mut(&mut |cond, templates| {
+        substitute_in(cond, resolved, templates, &mut Vec::new(), &mut unresolved)
+    })?;
+    Ok(unresolved)
+}
+
+fn substitute_in(
+    cond: &mut ConditionExpr,
+    resolved: &[(Ident, TokenStream)],
+    templates: &[Rc<CondTemplate>],
+    expanding: &mut Vec<String>,
+    unresolved: &mut Option<Ident>,
+) -> ParseResult<()> {
+    let (name, key) = match cond {
+        ConditionExpr::Named(name) => (name.clone(), format!("@{}", name)),
+        ConditionExpr::Call(name, _) => (name.clone(), format!("{}(..)", name)),
+        _ => {
+            for child in cond.children_mut() {
+                substitute_in(child, resolved, templates, expanding, unresolved)?;
+            }
+            return Ok(());
+        }
+    };
+    if expanding.contains(&key) {
+        return Err(syn::Error::new(
+            name.span(),
+            format!("the condition `{}` refers to itself", key),
+        ));
+    }
+    let expansion = match &*cond {
+        ConditionExpr::Call(_, args) => {
+            // the innermost declaration wins
+            let template = templates
+                .iter()
+                .rev()
+                .find(|template| *template.name() == name)
+                .ok_or_else(|| {
+                    syn::Error::new(
+                        name.span(),
+                        format!("no condition template named `{}` is declared", name),
+                    )
+                })?;
+            template.expand_call(&name, args)?
+        }
+        _ => match resolved.iter().find(|(resolved, _)| *resolved == name) {
+            Some((_, definition)) => Parser::parse2(
+                |input: ParseStream| grammar::parse_condition(&input),
+                grammar::respan(definition.clone(), name.span()),
+            )?,
+            None => {
+                unresolved.get_or_insert(name);
+                return Ok(());
+            }
+        },
+    };
+    *cond = expansion;
+    expanding.push(key);
+    substitute_in(cond, resolved, templates, expanding, unresolved)?;
+    expanding.pop();
+    Ok(())
+}
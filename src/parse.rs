@@ -1,44 +1,326 @@
 use {
-    crate::{grammar, ParseResult},
+    crate::{
+        dispatch::{self, Dispatch},
+        grammar::{self, ConditionExpr},
+        template::CondTemplate,
+        ParseResult,
+    },
+    proc_macro2::Delimiter,
     quote::quote,
+    std::rc::Rc,
     syn::{
         braced,
         parse::{Parse, ParseStream},
         punctuated::Punctuated,
-        Attribute, Ident, Item, Token, Visibility,
+        AttrStyle, Attribute, Ident, Item, LitStr, Path, Token, Visibility,
     },
 };
 
 pub(crate) struct PragmaInput {
     pub(crate) items: Punctuated<PragmaItem, Token![;]>,
+    /// the condition templates declared in this block
+    pub(crate) templates: Vec<Rc<CondTemplate>>,
+    /// the inner attributes at the start of a module body
+    pub(crate) inner_attrs: Vec<InnerAttr>,
+}
+
+/// An inner attribute (`#![..]` or `//!`), possibly with a condition: `(if test) #![..]`
+pub(crate) struct InnerAttr {
+    condition: Option<ConditionExpr>,
+    attr: Attribute,
+}
+
+impl PragmaInput {
+    /// call `f` on the condition of every item, including the items of nested modules, along with
+    /// the condition templates that are in scope for the item
+    pub(crate) fn for_each_condition_mut<E>(
+        &mut self,
+        f: &mut impl FnMut(&mut ConditionExpr, &[Rc<CondTemplate>]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        self.for_each_condition_in_scope(&[], f)
+    }
+
+    fn for_each_condition_in_scope<E>(
+        &mut self,
+        outer: &[Rc<CondTemplate>],
+        f: &mut impl FnMut(&mut ConditionExpr, &[Rc<CondTemplate>]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let mut scope = outer.to_vec();
+        scope.extend(self.templates.iter().cloned());
+        for attr in self.inner_attrs.iter_mut() {
+            if let Some(cond) = &mut attr.condition {
+                f(cond, &scope)?;
+            }
+        }
+        for item in self.items.iter_mut() {
+            match &mut item.condition {
+                Some(PragmaCondition::If(cond)) | Some(PragmaCondition::ElseIf(cond)) => {
+                    f(cond, &scope)?
+                }
+                Some(PragmaCondition::Else) | None => {}
+            }
+            match &mut item.content {
+                PragmaItemContent::Mod { content, .. } | PragmaItemContent::Block(content) => {
+                    content.for_each_condition_in_scope(&scope, f)?
+                }
+                PragmaItemContent::Normal(_)
+                | PragmaItemContent::Dispatch(_)
+                | PragmaItemContent::ModFile { .. } => {}
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Parse for PragmaInput {
     fn parse(input: ParseStream) -> ParseResult<Self> {
-        let mut items = Punctuated::new();
-        while !input.is_empty() {
-            let itm = input.parse::<PragmaItem>()?;
-            items.push(itm);
-            if input.peek(Token![;]) {
-                input.parse::<Token![;]>()?;
+        if input.peek(Token![if]) {
+            // `pragma!(if cond; ..)` puts every item of the invocation in a block
+            input.parse::<Token![if]>()?;
+            let condition = grammar::parse_condition(&input)?;
+            if let Some((runtime, _)) = dispatch::runtime_features(&condition) {
+                return Err(syn::Error::new(
+                    runtime.span(),
+                    "`or runtime` can only be used on functions",
+                ));
+            }
+            input.parse::<Token![;]>()?;
+            let block = parse_items(input)?;
+            let mut items = Punctuated::new();
+            items.push(PragmaItem {
+                attrs: Vec::new(),
+                visibility: Visibility::Inherited,
+                condition: Some(PragmaCondition::If(condition)),
+                content: PragmaItemContent::Block(block),
+            });
+            return Ok(PragmaInput {
+                items,
+                templates: Vec::new(),
+                inner_attrs: Vec::new(),
+            });
+        }
+        parse_items(input)
+    }
+}
+
+/// parse a sequence of items and `cond` template declarations, making sure that every `(else ..)`
+/// continues an `(if ..)` chain
+fn parse_items(input: ParseStream) -> ParseResult<PragmaInput> {
+    let mut items: Punctuated<PragmaItem, Token![;]> = Punctuated::new();
+    let mut templates = Vec::new();
+    while !input.is_empty() {
+        if is_match(input) {
+            parse_match(input, &mut items)?;
+            continue;
+        }
+        if CondTemplate::peek(input) {
+            templates.push(Rc::new(input.parse()?));
+            continue;
+        }
+        let span = input.span();
+        let itm = input.parse::<PragmaItem>()?;
+        if itm.continues_chain() {
+            let in_chain = matches!(
+                items.last().and_then(|prev| prev.condition.as_ref()),
+                Some(PragmaCondition::If(_)) | Some(PragmaCondition::ElseIf(_))
+            );
+            if !in_chain {
+                return Err(syn::Error::new(
+                    span,
+                    "`else` must follow an `(if ..)` or `(else if ..)` item",
+                ));
+            }
+        }
+        items.push(itm);
+        if input.peek(Token![;]) {
+            input.parse::<Token![;]>()?;
+        }
+    }
+    Ok(PragmaInput {
+        items,
+        templates,
+        inner_attrs: Vec::new(),
+    })
+}
+
+/// parse the inner attributes at the start of a module body: `#![..]`, `//!` and
+/// `(if cond) #![..]`
+fn parse_inner_attrs(input: ParseStream) -> ParseResult<Vec<InnerAttr>> {
+    let mut attrs = Vec::new();
+    loop {
+        if input.peek(Token![#]) && input.peek2(Token![!]) {
+            attrs.push(InnerAttr {
+                condition: None,
+                attr: parse_inner_attr(input)?,
+            });
+        } else if is_conditional_inner_attr(input) {
+            let content;
+            let paren = syn::parenthesized!(content in input);
+            let condition = match parse_item_condition(&content)? {
+                PragmaCondition::If(cond) => cond,
+                PragmaCondition::ElseIf(_) | PragmaCondition::Else => {
+                    return Err(syn::Error::new(
+                        paren.span,
+                        "inner attributes can't be part of an `else` chain",
+                    ))
+                }
+            };
+            attrs.push(InnerAttr {
+                condition: Some(condition),
+                attr: parse_inner_attr(input)?,
+            });
+        } else {
+            return Ok(attrs);
+        }
+    }
+}
+
+/// check if the next tokens are `(..) #!`
+fn is_conditional_inner_attr(input: ParseStream) -> bool {
+    input
+        .cursor()
+        .group(Delimiter::Parenthesis)
+        .and_then(|(_, _, rest)| rest.punct())
+        .filter(|(pound, _)| pound.as_char() == '#')
+        .and_then(|(_, rest)| rest.punct())
+        .is_some_and(|(bang, _)| bang.as_char() == '!')
+}
+
+/// parse a single `#![..]`
+fn parse_inner_attr(input: ParseStream) -> ParseResult<Attribute> {
+    let content;
+    Ok(Attribute {
+        pound_token: input.parse()?,
+        style: AttrStyle::Inner(input.parse()?),
+        bracket_token: syn::bracketed!(content in input),
+        path: content.call(Path::parse_mod_style)?,
+        tokens: content.parse()?,
+    })
+}
+
+/// check if the next item is a `(match key) { .. }` block
+fn is_match(input: ParseStream) -> bool {
+    input
+        .cursor()
+        .group(Delimiter::Parenthesis)
+        .and_then(|(inner, _, _)| inner.ident())
+        .is_some_and(|(ident, _)| ident == "match")
+}
+
+/// parse a `(match key) { "a" => item, "b" | "c" => item, _ => item }` block
+///
+/// Each arm is lowered into an item of an `if`/`else if`/`else` chain on `key = "value"`, so the
+/// first matching arm wins and `_` covers every value that wasn't listed.
+fn parse_match(
+    input: ParseStream,
+    items: &mut Punctuated<PragmaItem, Token![;]>,
+) -> ParseResult<()> {
+    let content;
+    let _paren = syn::parenthesized!(content in input);
+    content.parse::<Token![match]>()?;
+    let key: Ident = content.parse()?;
+    let arms;
+    let _brace = braced!(arms in input);
+
+    let mut first = true;
+    let mut has_wildcard = false;
+    while !arms.is_empty() {
+        if has_wildcard {
+            return Err(syn::Error::new(
+                arms.span(),
+                "the `_` arm must be the last arm of a `match`",
+            ));
+        }
+        // parse the pattern: `_` or `"a" | "b" | ..`
+        let condition = if arms.peek(Token![_]) {
+            let wildcard = arms.parse::<Token![_]>()?;
+            if first {
+                return Err(syn::Error::new(
+                    wildcard.span,
+                    "a `match` needs at least one arm before `_`",
+                ));
+            }
+            has_wildcard = true;
+            PragmaCondition::Else
+        } else {
+            let mut values = vec![ConditionExpr::KeyVal(key.clone(), arms.parse()?)];
+            while arms.peek(Token![|]) {
+                arms.parse::<Token![|]>()?;
+                values.push(ConditionExpr::KeyVal(key.clone(), arms.parse()?));
             }
+            let cond = if values.len() == 1 {
+                values.remove(0)
+            } else {
+                ConditionExpr::Any(values)
+            };
+            if first {
+                PragmaCondition::If(cond)
+            } else {
+                PragmaCondition::ElseIf(cond)
+            }
+        };
+        arms.parse::<Token![=>]>()?;
+        let span = arms.span();
+        let mut itm = arms.parse::<PragmaItem>()?;
+        if itm.condition.is_some() {
+            return Err(syn::Error::new(
+                span,
+                "the items of `match` arms can't have their own condition",
+            ));
         }
-        Ok(PragmaInput { items })
+        itm.condition = Some(condition);
+        items.push(itm);
+        if arms.peek(Token![,]) {
+            arms.parse::<Token![,]>()?;
+        }
+        first = false;
     }
+    Ok(())
 }
 
 pub(crate) enum PragmaItemContent {
-    Normal(Item),
-    Mod { ident: Ident, content: PragmaInput },
+    Normal(Box<Item>),
+    /// a function with `(if target_feature = ".." or runtime)`
+    Dispatch(Box<Dispatch>),
+    /// `(if cond) { items }`: the items keep their paths, and each of them is gated on `cond`
+    Block(PragmaInput),
+    Mod {
+        ident: Ident,
+        content: PragmaInput,
+    },
+    /// `mod name;` or `mod name = "path";`, a module that is loaded from a file
+    ModFile {
+        ident: Ident,
+        path: Option<LitStr>,
+    },
+}
+
+/// The condition attached to an item
+pub(crate) enum PragmaCondition {
+    /// `(if cond)`: starts a new chain
+    If(ConditionExpr),
+    /// `(else if cond)`: continues the chain started by the previous items
+    ElseIf(ConditionExpr),
+    /// `(else)`: terminates the chain
+    Else,
 }
 
 pub(crate) struct PragmaItem {
     pub(crate) attrs: Vec<Attribute>,
     pub(crate) visibility: Visibility,
-    pub(crate) condition: Option<grammar::ConditionExpr>,
+    pub(crate) condition: Option<PragmaCondition>,
     pub(crate) content: PragmaItemContent,
 }
 
+impl PragmaItem {
+    fn continues_chain(&self) -> bool {
+        matches!(
+            self.condition,
+            Some(PragmaCondition::ElseIf(_)) | Some(PragmaCondition::Else)
+        )
+    }
+}
+
 impl Parse for PragmaItem {
     fn parse(input: ParseStream) -> ParseResult<Self> {
         // parse attributes
@@ -46,34 +328,79 @@ impl Parse for PragmaItem {
         // parse visibility
         let visibility: Visibility = input.parse()?;
 
-        // check if we have `(if ...)`
+        // check if we have `(if ...)`, `(unless ...)`, `(else if ...)` or `(else)`
         let condition = if input.peek(syn::token::Paren) {
             let content;
             let _paren = syn::parenthesized!(content in input);
-            content.parse::<Token![if]>()?;
-            let cond_expr = grammar::parse_condition(&&content)?;
-            Some(cond_expr)
+            Some(parse_item_condition(&content)?)
         } else {
             None
         };
+        let runtime = match &condition {
+            Some(PragmaCondition::If(cond)) => dispatch::runtime_features(cond),
+            Some(PragmaCondition::ElseIf(cond)) => match dispatch::runtime_features(cond) {
+                Some((runtime, _)) => {
+                    return Err(syn::Error::new(
+                        runtime.span(),
+                        "`or runtime` can't be used in an `else` chain",
+                    ))
+                }
+                None => None,
+            },
+            _ => None,
+        };
+        if let Some((runtime, _)) = &runtime {
+            if input.peek(syn::token::Brace) || input.peek(Token![mod]) {
+                return Err(syn::Error::new(
+                    runtime.span(),
+                    "`or runtime` can only be used on functions",
+                ));
+            }
+        }
 
-        if input.peek(Token![mod]) {
+        if input.peek(syn::token::Brace) {
+            // parse a block of items
+            if !matches!(visibility, Visibility::Inherited) {
+                return Err(syn::Error::new(
+                    input.span(),
+                    "a block can't have a visibility; put it on the items of the block instead",
+                ));
+            }
+            let content_stream;
+            let _brace = braced!(content_stream in input);
+            let mut block = parse_items(&content_stream)?;
+            prepend_attrs(&mut block, &attrs);
+            Ok(PragmaItem {
+                attrs: Vec::new(),
+                visibility,
+                condition,
+                content: PragmaItemContent::Block(block),
+            })
+        } else if input.peek(Token![mod]) {
             // parse a module
             input.parse::<Token![mod]>()?;
             let ident: Ident = input.parse()?;
+            if input.peek(Token![;]) || input.peek(Token![=]) {
+                // parse `mod name;` or `mod name = "path";`
+                let path = if input.peek(Token![=]) {
+                    input.parse::<Token![=]>()?;
+                    Some(input.parse()?)
+                } else {
+                    None
+                };
+                input.parse::<Token![;]>()?;
+                return Ok(PragmaItem {
+                    attrs,
+                    visibility,
+                    condition,
+                    content: PragmaItemContent::ModFile { ident, path },
+                });
+            }
             let content_stream;
             let _brace = braced!(content_stream in input);
-
-            let mut items = Punctuated::new();
-            while !content_stream.is_empty() {
-                let itm = content_stream.parse::<PragmaItem>()?;
-                items.push(itm);
-                if content_stream.peek(Token![;]) {
-                    content_stream.parse::<Token![;]>()?;
-                }
-            }
-
-            let inner_input = PragmaInput { items };
+            let inner_attrs = parse_inner_attrs(&content_stream)?;
+            let mut inner_input = parse_items(&content_stream)?;
+            inner_input.inner_attrs = inner_attrs;
             Ok(PragmaItem {
                 attrs,
                 visibility,
@@ -83,6 +410,18 @@ impl Parse for PragmaItem {
                     content: inner_input,
                 },
             })
+        } else if let Some((runtime, features)) = runtime {
+            // a function that is dispatched at runtime is always available, so it has no
+            // condition of its own
+            let item: Item = input.parse()?;
+            Ok(PragmaItem {
+                attrs,
+                visibility,
+                condition: None,
+                content: PragmaItemContent::Dispatch(Box::new(Dispatch::new(
+                    &runtime, features, item,
+                )?)),
+            })
         } else {
             // normal item
             let item: Item = input.parse()?;
@@ -90,14 +429,89 @@ impl Parse for PragmaItem {
                 attrs,
                 visibility,
                 condition,
-                content: PragmaItemContent::Normal(item),
+                content: PragmaItemContent::Normal(Box::new(item)),
             })
         }
     }
 }
 
+/// `cond` (if any) combined with the condition of the enclosing block
+fn within(outer: &ConditionExpr, cond: Option<ConditionExpr>) -> ConditionExpr {
+    match cond {
+        Some(cond) => ConditionExpr::All(vec![outer.clone(), cond]),
+        None => outer.clone(),
+    }
+}
+
+/// the attributes of a block apply to each of its items, including the items of nested blocks
+fn prepend_attrs(block: &mut PragmaInput, attrs: &[Attribute]) {
+    for item in block.items.iter_mut() {
+        match &mut item.content {
+            PragmaItemContent::Block(block) => prepend_attrs(block, attrs),
+            _ => {
+                item.attrs.splice(0..0, attrs.iter().cloned());
+            }
+        }
+    }
+}
+
+/// parse the contents of `(if ...)`, `(unless ...)`, `(else if ...)` or `(else)`
+fn parse_item_condition(content: ParseStream) -> ParseResult<PragmaCondition> {
+    if content.peek(Token![else]) {
+        content.parse::<Token![else]>()?;
+        if content.peek(Token![if]) {
+            content.parse::<Token![if]>()?;
+            let cond_expr = grammar::parse_condition(&content)?;
+            Ok(PragmaCondition::ElseIf(cond_expr))
+        } else {
+            Ok(PragmaCondition::Else)
+        }
+    } else if grammar::peek_keyword(&content, "unless") {
+        content.parse::<Ident>()?;
+        let cond_expr = grammar::parse_condition(&content)?;
+        Ok(PragmaCondition::If(ConditionExpr::Not(Box::new(cond_expr))))
+    } else {
+        content.parse::<Token![if]>()?;
+        let cond_expr = grammar::parse_condition(&content)?;
+        Ok(PragmaCondition::If(cond_expr))
+    }
+}
+
+/// the disjunction of all the conditions seen so far in an `if`/`else` chain
+fn chain_condition(chain: &[ConditionExpr]) -> ConditionExpr {
+    match chain {
+        [single] => single.clone(),
+        _ => ConditionExpr::Any(chain.to_vec()),
+    }
+}
+
 pub(crate) fn process_pragma_input(input: PragmaInput) -> proc_macro2::TokenStream {
-    let tokens = input.items.into_iter().map(|item| {
+    process_items(input, None)
+}
+
+/// `outer` is the condition of the blocks that the items are in, which every item is gated on
+fn process_items(input: PragmaInput, outer: Option<&ConditionExpr>) -> proc_macro2::TokenStream {
+    // conditions of the `if`/`else if` chain we're currently in
+    let mut chain: Vec<ConditionExpr> = Vec::new();
+    let mut items = input.items.into_iter().peekable();
+    let mut tokens = Vec::new();
+
+    // inner attributes have to come first; conditional ones become `cfg_attr`s
+    let mut dependencies = Vec::new();
+    for InnerAttr { condition, attr } in input.inner_attrs {
+        match condition {
+            Some(cond) => {
+                dependencies.push(grammar::condition_dependencies(&cond));
+                let cond = grammar::condition_to_cfg(&cond);
+                let (path, meta) = (&attr.path, &attr.tokens);
+                tokens.push(quote! { #![cfg_attr(#cond, #path #meta)] });
+            }
+            None => tokens.push(quote! { #attr }),
+        }
+    }
+    tokens.extend(dependencies);
+
+    while let Some(item) = items.next() {
         let PragmaItem {
             attrs,
             visibility,
@@ -105,101 +519,155 @@ pub(crate) fn process_pragma_input(input: PragmaInput) -> proc_macro2::TokenStre
             content,
         } = item;
 
-        match content {
-            PragmaItemContent::Normal(item) => {
-                if let Some(cond) = condition {
-                    let main_condition = grammar::condition_to_cfg(&cond);
-                    let inverse_condition = quote! { not(#main_condition) };
-
-                    match &visibility {
-                        Visibility::Inherited => {
-                            // single version for (if condition) no visibility
-                            quote! {
-                                #[cfg(#main_condition)]
-                                #(#attrs)*
-                                #item
-                            }
-                        }
-                        _ => {
-                            // two versions for pub (if condition)
-                            let public_item = quote! {
-                                #[cfg(#main_condition)]
-                                #(#attrs)*
-                                #visibility #item
-                            };
-                            let private_item = quote! {
-                                #[cfg(#inverse_condition)]
-                                #(#attrs)*
-                                #item
-                            };
-                            quote! {
-                                #public_item
-                                #private_item
-                            }
-                        }
-                    }
-                } else {
-                    // unconditional item
-                    quote! {
-                        #(#attrs)*
-                        #visibility #item
-                    }
-                }
+        // compute the mutually exclusive condition for this item
+        let main_condition = match condition {
+            Some(PragmaCondition::If(cond)) => {
+                chain = vec![cond.clone()];
+                Some(cond)
+            }
+            Some(PragmaCondition::ElseIf(cond)) => {
+                let previous = chain_condition(&chain);
+                chain.push(cond.clone());
+                Some(ConditionExpr::All(vec![
+                    ConditionExpr::Not(Box::new(previous)),
+                    cond,
+                ]))
+            }
+            Some(PragmaCondition::Else) => {
+                let previous = chain_condition(&chain);
+                chain.clear();
+                Some(ConditionExpr::Not(Box::new(previous)))
+            }
+            None => {
+                chain.clear();
+                None
+            }
+        };
+        // the private fallback of a `pub (if ..)` item is only generated at the end of a chain,
+        // and covers every case where no branch of the chain matched
+        let chain_ends = !items.peek().is_some_and(PragmaItem::continues_chain);
+        let fallback_condition = if chain_ends && !chain.is_empty() {
+            Some(ConditionExpr::Not(Box::new(chain_condition(&chain))))
+        } else {
+            None
+        };
+        // inside a block, everything is also gated on the condition of the block
+        let (main_condition, fallback_condition) = match outer {
+            Some(outer) => (
+                Some(within(outer, main_condition)),
+                fallback_condition.map(|cond| within(outer, Some(cond))),
+            ),
+            None => (main_condition, fallback_condition),
+        };
+
+        let expanded = match content {
+            PragmaItemContent::Normal(item) => expand_item(
+                &attrs,
+                &visibility,
+                main_condition.as_ref(),
+                fallback_condition.as_ref(),
+                |vis| quote! { #vis #item },
+            ),
+            PragmaItemContent::Block(block) => process_items(block, main_condition.as_ref()),
+            PragmaItemContent::Dispatch(dispatch) => {
+                let dispatch = dispatch.expand();
+                expand_item(
+                    &attrs,
+                    &visibility,
+                    main_condition.as_ref(),
+                    fallback_condition.as_ref(),
+                    |vis| quote! { #vis #dispatch },
+                )
+            }
+            PragmaItemContent::ModFile { ident, path } => {
+                let path = path.map(|path| quote! { #[path = #path] });
+                expand_item(
+                    &attrs,
+                    &visibility,
+                    main_condition.as_ref(),
+                    fallback_condition.as_ref(),
+                    |vis| quote! { #path #vis mod #ident; },
+                )
             }
             PragmaItemContent::Mod {
                 ident,
                 content: inner_input,
             } => {
                 let inner_tokens = process_pragma_input(inner_input);
-                if let Some(cond) = condition {
-                    let main_condition = grammar::condition_to_cfg(&cond);
-                    let inverse_condition = quote! { not(#main_condition) };
-
-                    match &visibility {
-                        Visibility::Inherited => {
-                            quote! {
-                                #[cfg(#main_condition)]
-                                #(#attrs)*
-                                mod #ident {
-                                    #inner_tokens
-                                }
+                expand_item(
+                    &attrs,
+                    &visibility,
+                    main_condition.as_ref(),
+                    fallback_condition.as_ref(),
+                    |vis| {
+                        quote! {
+                            #vis mod #ident {
+                                #inner_tokens
                             }
                         }
-                        _ => {
-                            let public_item = quote! {
-                                #[cfg(#main_condition)]
-                                #(#attrs)*
-                                #visibility mod #ident {
-                                    #inner_tokens
-                                }
-                            };
-                            let private_item = quote! {
-                                #[cfg(#inverse_condition)]
-                                #(#attrs)*
-                                mod #ident {
-                                    #inner_tokens
-                                }
-                            };
-                            quote! {
-                                #public_item
-                                #private_item
-                            }
-                        }
-                    }
-                } else {
-                    // unconditional mod
-                    quote! {
-                        #(#attrs)*
-                        #visibility mod #ident {
-                            #inner_tokens
-                        }
-                    }
-                }
+                    },
+                )
             }
-        }
-    });
+        };
+        tokens.push(expanded);
+    }
 
     quote! {
         #(#tokens)*
     }
 }
+
+/// Generate the `#[cfg(..)]` gated versions of an item.
+///
+/// `body` renders the item with the given visibility. If the item has a visibility and a
+/// fallback condition, a private version is generated under the fallback condition.
+fn expand_item(
+    attrs: &[Attribute],
+    visibility: &Visibility,
+    main_condition: Option<&ConditionExpr>,
+    fallback_condition: Option<&ConditionExpr>,
+    body: impl Fn(&Visibility) -> proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let (main_condition, dependencies) = match main_condition {
+        Some(cond) => (
+            grammar::condition_to_cfg(cond),
+            grammar::condition_dependencies(cond),
+        ),
+        None => {
+            // unconditional item
+            let item = body(visibility);
+            return quote! {
+                #(#attrs)*
+                #item
+            };
+        }
+    };
+
+    match (visibility, fallback_condition) {
+        (Visibility::Inherited, _) | (_, None) => {
+            // single version for (if condition) with no visibility, or in the middle of a chain
+            let item = body(visibility);
+            quote! {
+                #dependencies
+                #[cfg(#main_condition)]
+                #(#attrs)*
+                #item
+            }
+        }
+        (_, Some(fallback_condition)) => {
+            // two versions for pub (if condition)
+            let inverse_condition = grammar::condition_to_cfg(fallback_condition);
+            let public_item = body(visibility);
+            let private_item = body(&Visibility::Inherited);
+            quote! {
+                #dependencies
+                #[cfg(#main_condition)]
+                #(#attrs)*
+                #public_item
+                #[cfg(#inverse_condition)]
+                #(#attrs)*
+                #private_item
+            }
+        }
+    }
+}
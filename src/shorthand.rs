@@ -0,0 +1,53 @@
+use {
+    crate::grammar,
+    proc_macro2::{Ident, TokenStream, TokenTree},
+    syn::ext::IdentExt,
+};
+
+/// Built-in platform shorthands and the cfg predicates they expand to
+///
+/// A user defined cfg option with the same name can still be referenced with a raw identifier,
+/// for example `r#linux`.
+const SHORTHANDS: &[(&str, &str)] = &[
+    ("linux", r#"target_os = "linux""#),
+    ("macos", r#"target_os = "macos""#),
+    ("windows", r#"target_os = "windows""#),
+    (
+        "apple",
+        r#"any(target_os = "macos", target_os = "ios", target_os = "tvos", target_os = "watchos")"#,
+    ),
+    (
+        "bsd",
+        r#"any(target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly")"#,
+    ),
+    ("x64", r#"target_arch = "x86_64""#),
+    ("arm64", r#"target_arch = "aarch64""#),
+    (
+        "wasm",
+        r#"any(target_arch = "wasm32", target_arch = "wasm64")"#,
+    ),
+    ("bits64", r#"target_pointer_width = "64""#),
+    ("debug", "debug_assertions"),
+    ("release", "not(debug_assertions)"),
+];
+
+/// check if `name` is a built-in shorthand
+pub(crate) fn is_shorthand(name: &str) -> bool {
+    SHORTHANDS.iter().any(|(shorthand, _)| *shorthand == name)
+}
+
+/// expand `key` into its cfg predicate. raw identifiers are never expanded, and are emitted without
+/// the `r#` prefix
+pub(crate) fn expand_key(key: &Ident) -> TokenStream {
+    let name = key.to_string();
+    if name.starts_with("r#") {
+        return TokenTree::Ident(Ident::new(&key.unraw().to_string(), key.span())).into();
+    }
+    match SHORTHANDS.iter().find(|(shorthand, _)| *shorthand == name) {
+        Some((_, predicate)) => {
+            let tokens: TokenStream = predicate.parse().expect("invalid shorthand predicate");
+            grammar::respan(tokens, key.span())
+        }
+        None => TokenTree::Ident(key.clone()).into(),
+    }
+}
@@ -0,0 +1,220 @@
+//! Parameterized condition templates
+//!
+//! ```text
+//! cond os_arch(o, a) = target_os = o and target_arch = a;
+//! (if os_arch("linux", "x86_64") or os_arch("macos", "aarch64")) fn f() {}
+//! ```
+//!
+//! A call is expanded by substituting the arguments for the parameters in the tokens of the
+//! template, and parsing the result as a condition.
+
+use {
+    crate::{
+        grammar::{self, ConditionExpr},
+        ParseResult,
+    },
+    proc_macro2::{Delimiter, Group, TokenStream, TokenTree},
+    syn::{
+        parse::{Parse, ParseStream, Parser},
+        punctuated::Punctuated,
+        Ident, Token,
+    },
+};
+
+/// `cond name(param, ..) = condition;`
+pub(crate) struct CondTemplate {
+    name: Ident,
+    params: Vec<Ident>,
+    body: TokenStream,
+}
+
+impl CondTemplate {
+    /// check if the next tokens are a `cond name(..)` declaration
+    pub(crate) fn peek(input: ParseStream) -> bool {
+        grammar::peek_keyword(&input, "cond") && input.peek2(Ident)
+    }
+
+    pub(crate) fn name(&self) -> &Ident {
+        &self.name
+    }
+
+    /// expand a call to this template
+    pub(crate) fn expand_call(
+        &self,
+        call: &Ident,
+        args: &[TokenStream],
+    ) -> ParseResult<ConditionExpr> {
+        if args.len() != self.params.len() {
+            return Err(syn::Error::new(
+                call.span(),
+                format!(
+                    "the condition `{}` takes {} argument{} but {} {} given",
+                    self.name,
+                    self.params.len(),
+                    if self.params.len() == 1 { "" } else { "s" },
+                    args.len(),
+                    if args.len() == 1 { "was" } else { "were" },
+                ),
+            ));
+        }
+        // the tokens of the template point at the call, and the arguments keep their own spans, so
+        // every error already points at the call or at the argument it comes from. the body is
+        // parsed inside a group at the call, so that running out of tokens points there as well
+        let mut body = Group::new(
+            Delimiter::Parenthesis,
+            substitute(
+                grammar::respan(self.body.clone(), call.span()),
+                &self.params,
+                args,
+            ),
+        );
+        body.set_span(call.span());
+        let parse = |input: ParseStream| {
+            let content;
+            syn::parenthesized!(content in input);
+            grammar::parse_condition(&&content)
+        };
+        Parser::parse2(parse, TokenTree::Group(body).into()).map_err(|e| {
+            e.into_iter()
+                .map(|e| {
+                    syn::Error::new(
+                        e.span(),
+                        format!("in the expansion of the condition `{}`: {}", self.name, e),
+                    )
+                })
+                .reduce(|mut errors, e| {
+                    errors.combine(e);
+                    errors
+                })
+                .expect("an error without messages")
+        })
+    }
+}
+
+impl Parse for CondTemplate {
+    fn parse(input: ParseStream) -> ParseResult<Self> {
+        input.parse::<Ident>()?; // `cond`
+        let name: Ident = input.parse()?;
+        let content;
+        let _paren = syn::parenthesized!(content in input);
+        let params: Vec<Ident> = Punctuated::<Ident, Token![,]>::parse_terminated(&content)?
+            .into_iter()
+            .collect();
+        if let Some(duplicate) = params
+            .iter()
+            .enumerate()
+            .find_map(|(i, param)| params[..i].iter().find(|p| *p == param).map(|_| param))
+        {
+            return Err(syn::Error::new(
+                duplicate.span(),
+                format!("the parameter `{}` is declared more than once", duplicate),
+            ));
+        }
+        input.parse::<Token![=]>()?;
+        // the body is everything up to the `;`
+        let mut body = TokenStream::new();
+        while !input.peek(Token![;]) {
+            if input.is_empty() {
+                return Err(input.error("expected `;` after the condition template"));
+            }
+            body.extend(std::iter::once(input.parse::<TokenTree>()?));
+        }
+        input.parse::<Token![;]>()?;
+        // make sure that the body is a valid condition where it's declared
+        Parser::parse2(
+            |input: ParseStream| grammar::parse_condition(&input),
+            body.clone(),
+        )?;
+        Ok(CondTemplate { name, params, body })
+    }
+}
+
+/// split the arguments of a call at top level commas
+pub(crate) fn parse_call_args(input: ParseStream) -> ParseResult<Vec<TokenStream>> {
+    let mut args = Vec::new();
+    while !input.is_empty() {
+        let mut arg = TokenStream::new();
+        while !input.is_empty() && !input.peek(Token![,]) {
+            arg.extend(std::iter::once(input.parse::<TokenTree>()?));
+        }
+        if arg.is_empty() {
+            return Err(input.error("expected an argument"));
+        }
+        args.push(arg);
+        if input.peek(Token![,]) {
+            input.parse::<Token![,]>()?;
+        }
+    }
+    Ok(args)
+}
+
+/// replace the parameters in `tokens` with their arguments. arguments that are made of more than
+/// one token are parenthesized, so that they keep their precedence
+fn substitute(tokens: TokenStream, params: &[Ident], args: &[TokenStream]) -> TokenStream {
+    tokens
+        .into_iter()
+        .flat_map(|token| -> TokenStream {
+            match token {
+                TokenTree::Ident(ident) => match params.iter().position(|param| *param == ident) {
+                    Some(i) if args[i].clone().into_iter().count() == 1 => args[i].clone(),
+                    Some(i) => {
+                        let mut group = Group::new(Delimiter::Parenthesis, args[i].clone());
+                        group.set_span(args[i].clone().into_iter().next().unwrap().span());
+                        TokenTree::Group(group).into()
+                    }
+                    None => TokenTree::Ident(ident).into(),
+                },
+                TokenTree::Group(group) => {
+                    let mut new =
+                        Group::new(group.delimiter(), substitute(group.stream(), params, args));
+                    new.set_span(group.span());
+                    TokenTree::Group(new).into()
+                }
+                token => token.into(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(template: &str, args: &str) -> ParseResult<ConditionExpr> {
+        let template: CondTemplate = syn::parse_str(template).unwrap();
+        let args = Parser::parse_str(parse_call_args, args).unwrap();
+        template.expand_call(&template.name, &args)
+    }
+
+    #[test]
+    fn arity_mismatch() {
+        let template = "cond os_arch(o, a) = target_os = o and target_arch = a;";
+        assert_eq!(
+            expand(template, r#""linux""#).err().unwrap().to_string(),
+            "the condition `os_arch` takes 2 arguments but 1 was given"
+        );
+        assert_eq!(
+            expand(
+                "cond not_os(o) = not(target_os = o);",
+                r#""linux", "macos", "ios""#
+            )
+            .err()
+            .unwrap()
+            .to_string(),
+            "the condition `not_os` takes 1 argument but 3 were given"
+        );
+        assert!(expand(template, r#""linux", "x86_64""#).is_ok());
+    }
+
+    #[test]
+    fn argument_errors() {
+        assert_eq!(
+            expand("cond os(o) = target_os = o;", "unix and")
+                .err()
+                .unwrap()
+                .to_string(),
+            "in the expansion of the condition `os`: expected a cfg value (a string, an identifier or an \
+             integer)"
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! The `#[when(cond)]` attribute
+//!
+//! ```text
+//! #[doc = ".."] #[when(cond)] pub fn f() {}
+//! => pragma! { #[doc = ".."] pub (if cond) fn f() {} }
+//! ```
+//!
+//! The item is rewritten into a `pragma!` item and expanded like one, so that it gets the same
+//! private fallback and can refer to named conditions.
+
+use {
+    crate::{named::PragmaInvocation, ParseResult},
+    proc_macro2::TokenStream,
+    quote::quote,
+    syn::{spanned::Spanned, AttrStyle, Attribute, Item, Visibility},
+};
+
+pub(crate) fn expand_when(condition: TokenStream, mut item: Item) -> ParseResult<TokenStream> {
+    let (attrs, visibility) = split_item(&mut item)?;
+    let input: PragmaInvocation = syn::parse2(quote! {
+        #(#attrs)*
+        #visibility (if #condition) #item
+    })?;
+    Ok(input.expand())
+}
+
+/// take the outer attributes and the visibility out of `item`, since they come before the
+/// condition in a `pragma!` item
+fn split_item(item: &mut Item) -> ParseResult<(Vec<Attribute>, Visibility)> {
+    macro_rules! split {
+        ($($variant:ident),* ; $($no_vis:ident),*) => {
+            match item {
+                $(Item::$variant(item) => Ok((
+                    take_outer(&mut item.attrs),
+                    std::mem::replace(&mut item.vis, Visibility::Inherited),
+                )),)*
+                $(Item::$no_vis(item) => {
+                    Ok((take_outer(&mut item.attrs), Visibility::Inherited))
+                })*
+                item => Err(syn::Error::new(
+                    item.span(),
+                    "`#[when]` can't be used on this item",
+                )),
+            }
+        };
+    }
+    split!(
+        Const, Enum, ExternCrate, Fn, Macro2, Mod, Static, Struct, Trait, TraitAlias, Type, Union,
+        Use;
+        ForeignMod, Impl, Macro
+    )
+}
+
+/// inner attributes (of a module or a function body) stay with the item
+fn take_outer(attrs: &mut Vec<Attribute>) -> Vec<Attribute> {
+    let (outer, inner) = std::mem::take(attrs)
+        .into_iter()
+        .partition(|attr| matches!(attr.style, AttrStyle::Outer));
+    *attrs = inner;
+    outer
+}
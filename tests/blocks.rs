@@ -0,0 +1,86 @@
+use pragma::pragma;
+
+pragma! {
+    (if unix) {
+        fn block_family() -> &'static str { "unix" }
+        pub (if target_pointer_width = "64") fn block_width() -> u8 { 64 }
+        (else) fn block_width() -> u8 { 0 }
+    }
+    (else if windows) {
+        fn block_family() -> &'static str { "windows" }
+        fn block_width() -> u8 { 0 }
+    }
+    (else) {
+        fn block_family() -> &'static str { "other" }
+        fn block_width() -> u8 { 0 }
+    }
+
+    #[allow(dead_code)]
+    (if not unix) {
+        fn never_used_on_unix() {}
+        (if target_pointer_width = "64") {
+            fn nested_block() {}
+        }
+    }
+
+    // the attributes of a block also apply to the items of nested blocks
+    #[allow(dead_code)]
+    (if unix) {
+        fn outer_unused() {}
+        (if unix) {
+            fn nested_unused() {}
+        }
+    }
+
+    (match target_os) {
+        "linux" => {
+            fn os_block() -> &'static str { "linux" }
+        },
+        _ => {
+            fn os_block() -> &'static str { "other" }
+        },
+    }
+}
+
+#[test]
+fn conditional_blocks() {
+    let family = if cfg!(unix) {
+        "unix"
+    } else if cfg!(windows) {
+        "windows"
+    } else {
+        "other"
+    };
+    assert_eq!(block_family(), family);
+    assert_eq!(
+        block_width(),
+        if cfg!(all(unix, target_pointer_width = "64")) {
+            64
+        } else {
+            0
+        }
+    );
+    let os = if cfg!(target_os = "linux") {
+        "linux"
+    } else {
+        "other"
+    };
+    assert_eq!(os_block(), os);
+}
+
+mod header {
+    use pragma::pragma;
+
+    pragma!(if any(unix, windows);
+        pub fn desktop_family() {}
+        pub (if target_pointer_width = "64") fn desktop_wide() {}
+    );
+}
+
+#[test]
+fn block_header() {
+    #[cfg(any(unix, windows))]
+    header::desktop_family();
+    #[cfg(all(any(unix, windows), target_pointer_width = "64"))]
+    header::desktop_wide();
+}
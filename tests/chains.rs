@@ -0,0 +1,48 @@
+use pragma::pragma;
+
+pragma! {
+    (if target_pointer_width = "16") struct Width {
+        bits: u16,
+    }
+    (else if target_pointer_width = "32") struct Width {
+        bits: u32,
+    }
+    (else) struct Width {
+        bits: u64,
+    }
+
+    (if test) fn mode() -> &'static str { "test" }
+    (else) fn mode() -> &'static str { "not test" }
+
+    /// public on 16/32-bit targets, private everywhere else
+    pub (if target_pointer_width = "16") fn narrow() {}
+    pub (else if target_pointer_width = "32") fn narrow() {}
+}
+
+#[test]
+fn else_branches_are_exclusive() {
+    let w = Width { bits: 0 };
+    assert_eq!(std::mem::size_of_val(&w.bits) * 8, usize::BITS as usize);
+    assert_eq!(mode(), "test");
+    narrow();
+}
+
+pragma! {
+    (match target_os) {
+        "linux" => fn os() -> &'static str { "linux" },
+        "macos" | "ios" => fn os() -> &'static str { "apple" },
+        _ => fn os() -> &'static str { "other" },
+    }
+}
+
+#[test]
+fn match_arms() {
+    let expected = if cfg!(target_os = "linux") {
+        "linux"
+    } else if cfg!(any(target_os = "macos", target_os = "ios")) {
+        "apple"
+    } else {
+        "other"
+    };
+    assert_eq!(os(), expected);
+}
@@ -0,0 +1,285 @@
+use pragma::pragma;
+
+pragma! {
+    (if target_pointer_width in ["16", "32", "64"]) fn known_width() -> bool { true }
+    (else) fn known_width() -> bool { false }
+
+    (if target_os != "none") fn hosted() -> bool { true }
+    (else) fn hosted() -> bool { false }
+}
+
+#[test]
+fn membership_and_inequality() {
+    assert!(known_width());
+    assert_eq!(hosted(), cfg!(not(target_os = "none")));
+}
+
+pragma! {
+    (if target_pointer_width = 64 or target_pointer_width = 32) fn common_width() -> bool { true }
+    (else) fn common_width() -> bool { false }
+
+    (if target_os = linux) fn linux() -> bool { true }
+    (else) fn linux() -> bool { false }
+}
+
+#[test]
+fn unquoted_values() {
+    assert_eq!(
+        common_width(),
+        cfg!(any(
+            target_pointer_width = "64",
+            target_pointer_width = "32"
+        ))
+    );
+    assert_eq!(linux(), cfg!(target_os = "linux"));
+}
+
+pragma! {
+    (if unix && !test || windows) fn c_style() -> bool { true }
+    (else) fn c_style() -> bool { false }
+
+    (if not test and not(debug_assertions)) fn word_style() -> bool { true }
+    (else) fn word_style() -> bool { false }
+
+    (unless test) fn outside_tests() {}
+    (else) fn inside_tests() {}
+}
+
+#[test]
+fn c_style_operators() {
+    assert_eq!(c_style(), cfg!(windows));
+    assert!(!word_style());
+    inside_tests();
+}
+
+pragma! {
+    (if all(unix, target_pointer_width = "64") or any(windows, cfg(test and debug_assertions))) fn native() -> bool { true }
+    (else) fn native() -> bool { false }
+
+    (if all()) fn always() {}
+    (if any()) fn never() {}
+}
+
+#[test]
+fn native_cfg_syntax() {
+    assert!(native());
+    always();
+}
+
+pragma! {
+    (if linux or apple or bsd) fn shorthand_os() -> bool { true }
+    (else) fn shorthand_os() -> bool { false }
+
+    (if (x64 or arm64 or wasm) and bits64) fn shorthand_arch() -> bool { true }
+    (else) fn shorthand_arch() -> bool { false }
+
+    (if debug) fn profile() -> &'static str { "debug" }
+    (else if release) fn profile() -> &'static str { "release" }
+
+    (if r#windows) fn raw_windows() -> bool { true }
+    (else) fn raw_windows() -> bool { false }
+}
+
+#[test]
+fn platform_shorthands() {
+    assert_eq!(
+        shorthand_os(),
+        cfg!(any(
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "tvos",
+            target_os = "watchos",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "openbsd",
+            target_os = "dragonfly"
+        ))
+    );
+    assert_eq!(
+        shorthand_arch(),
+        cfg!(all(
+            any(
+                target_arch = "x86_64",
+                target_arch = "aarch64",
+                target_arch = "wasm32",
+                target_arch = "wasm64"
+            ),
+            target_pointer_width = "64"
+        ))
+    );
+    assert_eq!(
+        profile(),
+        if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        }
+    );
+    assert_eq!(raw_windows(), cfg!(windows));
+}
+
+pragma! {
+    (if target "x86_64-unknown-linux-gnu") fn triple() -> &'static str { "x86_64-unknown-linux-gnu" }
+    (else if target "aarch64-apple-*") fn triple() -> &'static str { "aarch64-apple-*" }
+    (else if target "*-pc-windows-msvc") fn triple() -> &'static str { "*-pc-windows-msvc" }
+    (else if target "aarch64-linux-android") fn triple() -> &'static str { "aarch64-linux-android" }
+    (else) fn triple() -> &'static str { "other" }
+}
+
+#[test]
+fn target_triples() {
+    let expected = if cfg!(all(
+        target_arch = "x86_64",
+        target_vendor = "unknown",
+        target_os = "linux",
+        target_env = "gnu"
+    )) {
+        "x86_64-unknown-linux-gnu"
+    } else if cfg!(all(target_arch = "aarch64", target_vendor = "apple")) {
+        "aarch64-apple-*"
+    } else if cfg!(all(
+        target_vendor = "pc",
+        target_os = "windows",
+        target_env = "msvc"
+    )) {
+        "*-pc-windows-msvc"
+    } else if cfg!(all(target_arch = "aarch64", target_os = "android")) {
+        "aarch64-linux-android"
+    } else {
+        "other"
+    };
+    assert_eq!(triple(), expected);
+}
+
+pragma! {
+    (if target_os ~ "*bsd") fn glob_bsd() -> bool { true }
+    (else) fn glob_bsd() -> bool { false }
+
+    (if target_arch ~ "x86*" or target_arch ~ "aarch6?") fn glob_arch() -> bool { true }
+    (else) fn glob_arch() -> bool { false }
+}
+
+#[test]
+fn glob_conditions() {
+    assert_eq!(
+        glob_bsd(),
+        cfg!(any(
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "openbsd"
+        ))
+    );
+    assert_eq!(
+        glob_arch(),
+        cfg!(any(
+            target_arch = "x86",
+            target_arch = "x86_64",
+            target_arch = "aarch64"
+        ))
+    );
+}
+
+pragma! {
+    (if rustc >= "1.31" and rustc < "100.0.0") fn modern_rustc() {}
+    (if rustc < "1.0") fn ancient_rustc() {}
+}
+
+#[test]
+fn rustc_version() {
+    modern_rustc();
+}
+
+pragma! {
+    (if env "CARGO_PKG_NAME" = "pragma" and env "CARGO_MANIFEST_DIR") fn built_by_cargo() {}
+    (if env "PRAGMA_SURELY_UNSET_VARIABLE") fn never_built() {}
+}
+
+#[test]
+fn env_conditions() {
+    built_by_cargo();
+}
+
+pragma! {
+    cond os_arch(o, a) = target_os = o and target_arch = a;
+    cond either(x, y) = x or y;
+
+    (if os_arch("linux", "x86_64") or os_arch(macos, aarch64)) fn templated() -> bool { true }
+    (else) fn templated() -> bool { false }
+
+    (if either(unix and target_pointer_width = "64", windows)) fn templated_either() -> bool { true }
+    (else) fn templated_either() -> bool { false }
+
+    mod templates_in_mod {
+        cond inner(o) = os_arch(o, "x86_64");
+        (if inner(linux)) pub fn inner_linux() -> bool { true }
+        (else) pub fn inner_linux() -> bool { false }
+    }
+}
+
+#[test]
+fn condition_templates() {
+    assert_eq!(
+        templated(),
+        cfg!(any(
+            all(target_os = "linux", target_arch = "x86_64"),
+            all(target_os = "macos", target_arch = "aarch64")
+        ))
+    );
+    assert_eq!(
+        templated_either(),
+        cfg!(any(all(unix, target_pointer_width = "64"), windows))
+    );
+    assert_eq!(
+        templates_in_mod::inner_linux(),
+        cfg!(all(target_os = "linux", target_arch = "x86_64"))
+    );
+}
+
+pragma! {
+    (if unix xor windows) fn xor_os() -> bool { true }
+    (else) fn xor_os() -> bool { false }
+
+    (if unix ^ target_pointer_width = "64") fn xor_c_style() -> bool { true }
+    (else) fn xor_c_style() -> bool { false }
+
+    (if windows implies target_env = "msvc") fn msvc_if_windows() -> bool { true }
+    (else) fn msvc_if_windows() -> bool { false }
+
+    (if exactly_one_of(unix, windows, target_os = "unknown")) fn one_family() -> bool { true }
+    (else) fn one_family() -> bool { false }
+
+    (if at_most_one_of(unix, windows, debug_assertions)) fn at_most_one() -> bool { true }
+    (else) fn at_most_one() -> bool { false }
+
+    (if exactly_one_of()) fn exactly_one_of_nothing() -> bool { true }
+    (else) fn exactly_one_of_nothing() -> bool { false }
+
+    // `or` binds tighter than `implies`, `xor` binds tighter than `or` and `and` binds tightest
+    (if not unix implies windows or unix xor unix and windows) fn precedence() -> bool { true }
+    (else) fn precedence() -> bool { false }
+}
+
+#[test]
+fn logical_operators() {
+    assert_eq!(xor_os(), cfg!(unix) != cfg!(windows));
+    assert_eq!(
+        xor_c_style(),
+        cfg!(unix) != cfg!(target_pointer_width = "64")
+    );
+    assert_eq!(
+        msvc_if_windows(),
+        !cfg!(windows) || cfg!(target_env = "msvc")
+    );
+    let families = [cfg!(unix), cfg!(windows), cfg!(target_os = "unknown")];
+    assert_eq!(one_family(), families.iter().filter(|x| **x).count() == 1);
+    let flags = [cfg!(unix), cfg!(windows), cfg!(debug_assertions)];
+    assert_eq!(at_most_one(), flags.iter().filter(|x| **x).count() <= 1);
+    assert!(!exactly_one_of_nothing());
+    let (unix, windows) = (cfg!(unix), cfg!(windows));
+    let implies = |a: bool, b: bool| !a || b;
+    assert_eq!(
+        precedence(),
+        implies(!unix, windows || (unix != (unix && windows)))
+    );
+}
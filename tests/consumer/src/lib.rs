@@ -0,0 +1 @@
+//! A crate that uses the named conditions of `tests/fixture`, with aliases of its own
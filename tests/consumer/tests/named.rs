@@ -0,0 +1,22 @@
+use {pragma::pragma, pragma_fixture::wide_desktop_os};
+
+pragma! {
+    (if @wide_desktop_os) fn wide_desktop() -> bool { true }
+    (else) fn wide_desktop() -> bool { false }
+}
+
+#[test]
+fn resolved_where_defined() {
+    // `wide_desktop` is the alias of `tests/fixture`, not the one of this crate
+    assert_eq!(
+        wide_desktop(),
+        cfg!(all(
+            any(
+                target_os = "linux",
+                target_os = "macos",
+                target_os = "windows"
+            ),
+            target_pointer_width = "64"
+        ))
+    );
+}
@@ -0,0 +1,44 @@
+use pragma::pragma;
+
+pragma! {
+    (if target_feature = "avx2" or runtime) fn sum(x: &[f32]) -> f32 {
+        x.iter().sum()
+    }
+
+    /// the dot product of `a` and `b`
+    pub (if target_feature = "avx2" and target_feature = "fma" or runtime)
+    fn dot(a: &[f32], mut b: Vec<f32>) -> f32 {
+        b.truncate(a.len());
+        a.iter().zip(&b).map(|(a, b)| a * b).sum()
+    }
+
+    (if target_feature = "sse4.1" or runtime)
+    fn first_or<'a, T, const N: usize>(x: &'a [T; N], default: &'a T) -> &'a T
+    where
+        T: PartialEq,
+    {
+        x.first().unwrap_or(default)
+    }
+
+    (if target_feature = "avx2" or runtime) unsafe fn read(x: *const u8) -> u8 {
+        *x
+    }
+
+    // features of other architectures are only used when they are enabled at compile time
+    (if target_feature = "neon" or runtime) fn neon_or_portable(x: u8) -> u8 {
+        x + 1
+    }
+
+}
+
+#[test]
+fn runtime_dispatch() {
+    assert_eq!(sum(&[1.0, 2.0, 3.5]), 6.5);
+    assert_eq!(dot(&[1.0, 2.0], vec![3.0, 4.0, 5.0]), 11.0);
+    assert_eq!(first_or(&[3u8, 4], &0), &3);
+    assert_eq!(first_or::<u8, 0>(&[], &0), &0);
+    assert_eq!(unsafe { read(&7) }, 7);
+    assert_eq!(neon_or_portable(1), 2);
+    // the detected features are cached after the first call
+    assert_eq!(sum(&[]), 0.0);
+}
@@ -0,0 +1,5 @@
+//! A crate whose `Cargo.toml` declares the features, ladders, aliases and Kconfig file that the
+//! tests in `tests/manifest.rs` check against
+
+// used by `tests/consumer`, whose `Cargo.toml` defines `wide_desktop` differently
+pragma::define_condition!(pub wide_desktop_os = wide_desktop);
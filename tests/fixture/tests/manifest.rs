@@ -0,0 +1,71 @@
+use pragma::pragma;
+
+pragma! {
+    (if api >= "api-v2") fn api_level() -> u8 { 2 }
+    (else if api >= "api-v1") fn api_level() -> u8 { 1 }
+    (else) fn api_level() -> u8 { 0 }
+
+    (if api < "api-v3" and api <= "api-v1" and not(api > "api-v1")) fn below_v2() {}
+}
+
+#[test]
+fn feature_ladders() {
+    let level = if cfg!(feature = "api-v2") {
+        2
+    } else if cfg!(feature = "api-v1") {
+        1
+    } else {
+        0
+    };
+    assert_eq!(api_level(), level);
+    #[cfg(not(feature = "api-v2"))]
+    below_v2();
+}
+
+pragma! {
+    (if CONFIG_FOO and CONFIG_DRIVER and CONFIG_BAR = "x" and CONFIG_WIDTH = 64) fn kconfig_enabled() {}
+    (if CONFIG_BAZ or CONFIG_UNDEFINED or CONFIG_BAR != "x") fn kconfig_disabled() {}
+    (else) fn kconfig_fallback() {}
+}
+
+#[test]
+fn kconfig_symbols() {
+    kconfig_enabled();
+    kconfig_fallback();
+}
+
+pragma! {
+    (if wide_desktop) fn alias() -> bool { true }
+    (else) fn alias() -> bool { false }
+}
+
+#[test]
+fn condition_aliases() {
+    assert_eq!(
+        alias(),
+        cfg!(all(
+            any(
+                target_os = "linux",
+                target_os = "macos",
+                target_os = "windows"
+            ),
+            target_pointer_width = "64"
+        ))
+    );
+}
+
+pragma! {
+    (if dep "api-v1") fn dep_enabled() -> bool { true }
+    (else) fn dep_enabled() -> bool { false }
+}
+
+pragma! {
+    (if dep "unicode-ident") fn optional_dep_enabled() -> bool { true }
+    (else) fn optional_dep_enabled() -> bool { false }
+}
+
+#[test]
+fn dependency_features() {
+    assert_eq!(dep_enabled(), cfg!(feature = "api-v1"));
+    assert_eq!(optional_dep_enabled(), cfg!(feature = "unicode-ident"));
+}
@@ -0,0 +1,69 @@
+//! `pragma!` used from other declarative macros, which wrap forwarded fragments in invisible
+//! groups
+
+use pragma::pragma;
+
+macro_rules! gated_meta {
+    ($cond:meta, $name:ident) => {
+        pragma! {
+            (if $cond) fn $name() -> bool { true }
+            (else) fn $name() -> bool { false }
+        }
+    };
+}
+
+macro_rules! gated_expr {
+    ($cond:expr, $name:ident) => {
+        pragma! {
+            (if not $cond) fn $name() -> bool { true }
+            (else) fn $name() -> bool { false }
+        }
+    };
+}
+
+macro_rules! gated_tt {
+    ($cond:tt and $other:tt, $name:ident) => {
+        pragma! {
+            (if $cond and $other) fn $name() -> bool { true }
+            (else) fn $name() -> bool { false }
+        }
+    };
+}
+
+macro_rules! gated_key {
+    ($key:path, $value:expr, $name:ident) => {
+        pragma! {
+            (if $key = $value) fn $name() -> bool { true }
+            (else) fn $name() -> bool { false }
+        }
+    };
+}
+
+macro_rules! gated_item {
+    ($vis:vis, $cond:expr, $item:item) => {
+        pragma! {
+            $vis (if $cond) $item
+        }
+    };
+}
+
+gated_meta!(all(unix, not(target_os = "none")), meta_form);
+gated_meta!(target_pointer_width = "64", meta_key_value);
+gated_expr!(windows || unix, negated_expr);
+gated_tt!((unix or windows) and (target_pointer_width = "64"), tt_form);
+gated_key!(target_os, "linux", forwarded_key);
+gated_item!(pub, unix && !windows, fn forwarded_item() {});
+
+#[test]
+fn forwarded_conditions() {
+    assert_eq!(meta_form(), cfg!(all(unix, not(target_os = "none"))));
+    assert_eq!(meta_key_value(), cfg!(target_pointer_width = "64"));
+    // `not $cond` negates the whole forwarded expression
+    assert_eq!(negated_expr(), !cfg!(any(windows, unix)));
+    assert_eq!(
+        tt_form(),
+        cfg!(all(any(unix, windows), target_pointer_width = "64"))
+    );
+    assert_eq!(forwarded_key(), cfg!(target_os = "linux"));
+    forwarded_item();
+}
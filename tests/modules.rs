@@ -0,0 +1,69 @@
+use pragma::pragma;
+
+pragma! {
+    (if unix) mod backend = "backends/unix.rs";
+    (else) mod backend = "backends/other.rs";
+}
+
+mod backends {
+    use pragma::pragma;
+
+    pragma! {
+        pub (if any(unix, windows)) mod plain;
+    }
+}
+
+#[test]
+fn file_modules() {
+    let expected = if cfg!(unix) { "unix" } else { "other" };
+    assert_eq!(backend::name(), expected);
+    backends::plain::loaded();
+}
+
+pragma::select_backend! {
+    /// the backend for the current platform
+    pub mod imp {
+        unix => "backends/imp/unix.rs",
+        windows => "backends/imp/windows.rs",
+        _ => "backends/imp/fallback.rs",
+    }
+}
+
+pragma::select_backend! {
+    mod required {
+        unix or not unix => "backends/imp/any.rs"
+    }
+}
+
+#[test]
+fn backend_selection() {
+    let expected = if cfg!(unix) {
+        "unix"
+    } else if cfg!(windows) {
+        "windows"
+    } else {
+        "other"
+    };
+    assert_eq!(imp::name(), expected);
+    assert_eq!(required::name(), "any");
+}
+
+pragma! {
+    (if any(unix, windows)) mod documented {
+        //! a module with inner attributes
+        #![allow(dead_code)]
+        (if test) #![allow(unused_imports)]
+        (unless windows) #![doc = "not on windows"]
+
+        use std::collections::HashMap;
+
+        fn unused() {}
+        pub fn used() {}
+    }
+}
+
+#[test]
+fn inner_attributes() {
+    #[cfg(any(unix, windows))]
+    documented::used();
+}
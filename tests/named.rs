@@ -0,0 +1,31 @@
+use pragma::{define_condition, pragma};
+
+define_condition!(pub wide = target_pointer_width = "64" or target_pointer_width = "32");
+define_condition!(pub(crate) wide_unix = @wide and unix);
+define_condition!(testing = test and debug_assertions);
+
+pragma! {
+    (if @wide_unix) fn named() -> bool { true }
+    (else) fn named() -> bool { false }
+
+    (if @testing and not @wide) mod narrow_tests {}
+    (else if @testing) fn testing() {}
+}
+
+mod nested {
+    pragma::pragma! {
+        (if @wide_unix) pub fn nested() -> bool { true }
+        (else) pub fn nested() -> bool { false }
+    }
+}
+
+#[test]
+fn named_conditions() {
+    let expected = cfg!(all(
+        any(target_pointer_width = "64", target_pointer_width = "32"),
+        unix
+    ));
+    assert_eq!(named(), expected);
+    assert_eq!(nested::nested(), expected);
+    testing();
+}
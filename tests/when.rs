@@ -0,0 +1,47 @@
+use pragma::when;
+
+pragma::define_condition!(unix_like = unix or target_os = "wasi");
+
+#[when(target_os = "linux" and not(target_pointer_width = "16"))]
+fn linux_only() -> bool {
+    true
+}
+
+/// public on unix, private everywhere else
+#[when(unix)]
+pub fn public_on_unix() -> u8 {
+    1
+}
+
+#[when(@unix_like)]
+#[derive(Debug, PartialEq)]
+pub struct Handle(u32);
+
+#[when(target_feature = "avx2" or runtime)]
+fn total(x: &[u32]) -> u32 {
+    x.iter().sum()
+}
+
+#[when(any(unix, windows))]
+mod desktop {
+    //! a module behind an attribute
+    #![allow(dead_code)]
+
+    pub fn inside() -> bool {
+        true
+    }
+
+    fn unused() {}
+}
+
+#[test]
+fn attribute_form() {
+    #[cfg(target_os = "linux")]
+    assert!(linux_only());
+    assert_eq!(public_on_unix(), 1);
+    #[cfg(any(unix, target_os = "wasi"))]
+    assert_eq!(Handle(1), Handle(1));
+    assert_eq!(total(&[1, 2, 3]), 6);
+    #[cfg(any(unix, windows))]
+    assert!(desktop::inside());
+}